@@ -1,18 +1,36 @@
 mod parse;
 mod types;
+mod inline;
+mod lineno;
 
 use crate::Error;
 
-pub use parse::{ParsePos, Identifier, Expr, RegexSubst, FunCall, Builtin};
+pub use parse::{ParsePos, Identifier, Expr, RegexSubst, FunCall, Builtin, Literal, ArithOp, ReduceOp, RangeMode, GroupOrder, JoinMode, Lambda, Let};
 
-pub fn compile(pgm: &str) -> Result<Expr, Error> {
+/// A program ready to be executed
+pub struct Program {
+    pub expr_tree: Expr,
+    /// Whether the program outputs a stream, or a single value
+    pub is_stream: bool,
+}
+
+pub fn compile(pgm: &str) -> Result<Program, Error> {
     eprintln!("Program: {}", pgm);
-    let mut expr_tree = parse::parse(&pgm)?;
+    let mut expr_tree = parse::parse(pgm)?;
     eprintln!("Parsed program: {}", expr_tree.pretty_print());
-    types::typecheck_program(&mut expr_tree)?;
-    Ok(expr_tree)
+    let is_stream = types::typecheck_program(&mut expr_tree)?;
+    let expr_tree = inline::inline_bindings(expr_tree)?;
+    lineno::check_lineno(&expr_tree)?;
+    Ok(Program { expr_tree, is_stream })
+}
+
+/// The type of a program, whether it can be executed or not
+pub fn type_of(pgm: &str) -> Result<String, Error> {
+    let mut expr_tree = parse::parse(pgm)?;
+    let pgm_type = types::typecheck_expr(&mut expr_tree)?;
+    Ok(pgm_type.to_string())
 }
 
-trait Position {
+pub trait Position {
     fn position(&self) -> ParsePos;
 }
\ No newline at end of file
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use crate::Error;
+
+use super::{parse::{Inlining, Variable}, Expr, FunCall, Lambda, Let};
+
+/// Substitutes the variables bound to streams and functions with their value.
+/// After this pass, the remaining variables are all bound to runtime values.
+pub fn inline_bindings(expr_tree: Expr) -> Result<Expr, Error> {
+    inline_expr(expr_tree, &mut HashMap::new())
+}
+
+struct Substitution {
+    value:   Expr,
+    kind:    Inlining,
+    n_uses:  usize,
+}
+
+fn inline_expr(expr: Expr, substitutions: &mut HashMap<usize, Substitution>) -> Result<Expr, Error> {
+    match expr {
+        Expr::Let(let_expr) if let_expr.inline.is_some() => {
+            let value = inline_expr(*let_expr.value, substitutions)?;
+
+            let var_id = let_expr.binding.var.id;
+            let substitution = Substitution { value, kind: let_expr.inline.unwrap(), n_uses: 0 };
+            substitutions.insert(var_id, substitution);
+            let body = inline_expr(*let_expr.body, substitutions);
+            substitutions.remove(&var_id);
+
+            body
+        }
+
+        Expr::Var(idn, var) => {
+            match substitutions.get_mut(&var.id) {
+                Some(substitution) => {
+                    if substitution.kind == Inlining::Stream && substitution.n_uses > 0 {
+                        return Err(Error::StreamVariableReused(idn));
+                    }
+                    substitution.n_uses += 1;
+                    Ok(fresh_copy(&substitution.value))
+                }
+                None =>
+                    Ok(Expr::Var(idn, var)),
+            }
+        }
+
+        Expr::FunCall(fcall) => {
+            let function = inline_expr(*fcall.function, substitutions)?;
+            let arguments =
+                fcall.arguments
+                    .into_iter()
+                    .map(|arg| inline_expr(arg, substitutions))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+            // The function may have been a variable bound to a partial application
+            Ok(FunCall::new_expr(function, arguments))
+        }
+
+        Expr::Lambda(lambda) => {
+            let body = inline_expr(*lambda.body, substitutions)?;
+            Ok(Expr::Lambda(Lambda { body: Box::new(body), ..lambda }))
+        }
+
+        Expr::Let(let_expr) => {
+            let value = inline_expr(*let_expr.value, substitutions)?;
+            let body = inline_expr(*let_expr.body, substitutions)?;
+            Ok(Expr::Let(Let { value: Box::new(value), body: Box::new(body), ..let_expr }))
+        }
+
+        other =>
+            Ok(other),
+    }
+}
+
+/// A copy of an expression with its own variables, so that the bindings of
+/// different copies don't interfere with each other at runtime
+fn fresh_copy(expr: &Expr) -> Expr {
+    let mut copy = expr.clone();
+    rename_bindings(&mut copy, &mut HashMap::new());
+    copy
+}
+
+fn rename_bindings(expr: &mut Expr, renamed: &mut HashMap<usize, Variable>) {
+    match expr {
+        Expr::Lambda(lambda) => {
+            for param in lambda.params.iter_mut() {
+                let fresh_var = Variable::fresh();
+                renamed.insert(param.var.id, fresh_var.clone());
+                param.var = fresh_var;
+            }
+        }
+        Expr::Let(let_expr) => {
+            let fresh_var = Variable::fresh();
+            renamed.insert(let_expr.binding.var.id, fresh_var.clone());
+            let_expr.binding.var = fresh_var;
+        }
+        Expr::Var(_idn, var) => {
+            if let Some(fresh_var) = renamed.get(&var.id) {
+                *var = fresh_var.clone();
+            }
+        }
+        _ => (),
+    }
+
+    for subtree in expr.children_mut() {
+        rename_bindings(subtree, renamed);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::Error;
+
+use super::{Builtin, Expr};
+
+/// Line numbers are only tracked as lines are read, so lineno is only meaningful in functions
+/// applied to items that come straight from the lines of stdin or a file.
+/// Buffering operators such as sort or tail would make it refer to some later line.
+pub fn check_lineno(expr_tree: &Expr) -> Result<(), Error> {
+    check_expr(expr_tree, false)
+}
+
+fn check_expr(expr: &Expr, allowed: bool) -> Result<(), Error> {
+    match expr {
+        Expr::Builtin(Builtin::Lineno, pos) if !allowed =>
+            Err(Error::UnsupportedLineno(*pos)),
+        Expr::FunCall(fcall) => {
+            check_expr(&fcall.function, allowed)?;
+            match (fcall.function.as_ref(), fcall.arguments.split_last()) {
+                // The functions are applied to each item as soon as the source produces it
+                (Expr::Builtin(b, _pos), Some((source, fn_args))) if applies_on_pull(b) => {
+                    let fn_allowed = is_line_chain(source);
+                    for arg in fn_args {
+                        check_expr(arg, fn_allowed)?;
+                    }
+                    check_expr(source, false)
+                }
+                _ => {
+                    for arg in &fcall.arguments {
+                        check_expr(arg, allowed)?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+        Expr::Lambda(lambda) =>
+            check_expr(&lambda.body, allowed),
+        Expr::Let(let_expr) => {
+            check_expr(&let_expr.value, allowed)?;
+            check_expr(&let_expr.body, allowed)
+        }
+        _ =>
+            Ok(()),
+    }
+}
+
+fn applies_on_pull(builtin: &Builtin) -> bool {
+    matches!(builtin,
+        Builtin::Map | Builtin::Filter | Builtin::FlatMap { .. } |
+        Builtin::TakeWhile | Builtin::DropWhile | Builtin::Between(_))
+}
+
+/// Whether the items of a stream are the lines of stdin or a file, with at most
+/// some operators that pass them along one at a time, as soon as they're read
+fn is_line_chain(stream: &Expr) -> bool {
+    match stream {
+        Expr::Builtin(Builtin::Stdin, _pos) =>
+            true,
+        Expr::FunCall(fcall) =>
+            match (fcall.function.as_ref(), fcall.arguments.last()) {
+                (Expr::Builtin(Builtin::File, _pos), _) =>
+                    true,
+                (Expr::Builtin(b, _pos), Some(source)) if passes_along(b) =>
+                    is_line_chain(source),
+                _ =>
+                    false,
+            },
+        _ =>
+            false,
+    }
+}
+
+fn passes_along(builtin: &Builtin) -> bool {
+    matches!(builtin,
+        Builtin::Map | Builtin::Filter | Builtin::FilterSome |
+        Builtin::Flatten | Builtin::FlatMap { stream: false } | Builtin::SplitLines |
+        Builtin::Take | Builtin::Drop | Builtin::TakeWhile | Builtin::DropWhile |
+        Builtin::Between(_) | Builtin::Every | Builtin::Bernoulli |
+        Builtin::Enumerate)
+}
@@ -2,16 +2,16 @@ mod token;
 
 pub use token::{ParsePos, Identifier, Token, RegexSubst};
 
-use std::{fmt::Display, iter::Peekable, ops::DerefMut};
+use std::{fmt::Display, iter::Peekable, ops::DerefMut, sync::atomic::{AtomicUsize, Ordering}};
 
 use crate::{error::Error, runtime};
 
-use super::Position;
+use super::{types::Type, Position};
 
 pub fn parse(pgm: &str) -> Result<Expr, Error> {
     let tokens = token::tokenize(pgm);
     let mut parsed = build_exp_tree(tokens)?;
-    name_resolution(&mut parsed)?;
+    name_resolution(&mut parsed, &mut Vec::new())?;
     Ok(parsed)
 }
 
@@ -21,38 +21,178 @@ pub fn parse(pgm: &str) -> Result<Expr, Error> {
 #[derive(Debug)]
 pub enum Expr {
     Builtin(Builtin, ParsePos),
+    Literal(Literal, ParsePos),
     UnresolvedIdentifier(Identifier),
     FunCall(FunCall),
-    ReadVar(runtime::StreamVar),
+    Lambda(Lambda),
+    Let(Let),
+    Var(Identifier, Variable),
+    // The position is the one of the stream the values are read from
+    ReadVar(runtime::StreamVar, ParsePos),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Builtin {
     /* Streams */
     Stdin,
+    File,
+    Concat,
+    Zip,
+    Interleave,
     Filter,
+    Between(RangeMode),
+    Context,
+    HashJoin(JoinMode),
     Map,
+    Flatten,
+    // Whether the function returns streams rather than lists, which is only known once typechecked
+    FlatMap { stream: bool },
+    SplitLines,
+    FilterSome,
+    Take,
+    Drop,
+    TakeWhile,
+    DropWhile,
+    Tail,
+    TailAfter,
+    Uniq,
+    UniqCount,
+    Distinct,
+    DistinctBy,
+    Sort,
+    SortBy,
+    ReverseSort,
+    ReverseSortBy,
+    GroupBy(GroupOrder),
+    BucketByTime,
+    BucketFill,
+    Top,
+    Frequencies,
+    Frequent,
+    Scan,
+    Enumerate,
+    Lines,
+    Window,
+    Chunks,
+    MovingAvg,
+    Histogram,
+    LogHistogram,
+    Bars,
+    Quantiles,
+    Sample,
+    Every,
+    Bernoulli,
     /* Scalars */
     RegexMatch(regex::Regex),
     RegexSubst(token::RegexSubst),
+    RegexCapture(regex::Regex),
     ToNumber,
+    TryToNumber,
+    ToInt,
+    TryToInt,
+    ToFloat,
+    TryToFloat,
+    Arith(ArithOp),
+    Lineno,
+    /* Lists */
+    Split,
+    Words,
+    At,
+    TryAt,
+    Len,
+    Join,
+    /* Optional values */
+    Default,
+    IsSome,
+    /* Tuples */
+    First,
+    Second,
+    /* Reducers */
+    Reduce(ReduceOp),
+    Fold,
+    Percentile,
+    /* List literals: [a, b, ...] */
+    List,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+/// How `between` selects ranges of items
+#[derive(Debug, Clone, Copy)]
+pub enum RangeMode {
+    // The start and end items are part of the range, and so are all the following ranges
+    Inclusive,
+    // Only the items strictly between the start and end items
+    Exclusive,
+    // Like Inclusive, but only the first range
+    Once,
+}
+
+/// The order of the groups output by `group_by`
+#[derive(Debug, Clone, Copy)]
+pub enum GroupOrder {
+    // In the order in which the keys were first seen
+    FirstSeen,
+    // By increasing key
+    Key,
+    // By increasing aggregated value, ties keeping the order in which the keys were first seen
+    Value,
+}
+
+/// Which items `join` outputs, given the matching items of the second stream
+#[derive(Debug, Clone, Copy)]
+pub enum JoinMode {
+    // Each pair of matching items
+    Inner,
+    // Also the items of the first stream that don't match anything, paired with a missing value
+    Left,
+    // Only the items of the first stream that don't match anything
+    Anti,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ReduceOp {
+    Count,
+    // Whether the items are floats, which is only known once typechecked
+    Sum { float: bool },
+    Min,
+    Max,
+    Avg,
+}
+
+#[derive(Debug, Clone)]
+pub enum Literal {
+    String(String),
+    Int(i64),
+    Float(f64),
 }
 
 impl Expr {
-    fn children_mut(&mut self) -> Vec<&mut Self> {
+    pub(super) fn children_mut(&mut self) -> Vec<&mut Self> {
         // TODO find a better way to avoid allocations
         match self {
             Self::Builtin(..) =>
                 // The Builtin expression is just a marker
                 // As such, it can never have children
                 Vec::new(),
+            Self::Literal(..) => Vec::new(),
             Self::UnresolvedIdentifier(_) => Vec::new(),
             Self::FunCall(fcall) => {
                 let mut children = vec![fcall.function.deref_mut()];
                 children.extend(fcall.arguments.iter_mut());
                 children
             },
-            Self::ReadVar(_) => Vec::new(),
+            Self::Lambda(lambda) => vec![lambda.body.deref_mut()],
+            Self::Let(let_expr) => vec![let_expr.value.deref_mut(), let_expr.body.deref_mut()],
+            Self::Var(..) => Vec::new(),
+            Self::ReadVar(..) => Vec::new(),
         }
     }
 
@@ -62,38 +202,53 @@ impl Expr {
     }
 }
 
+// Note: expressions are only cloned during compilation, at which point
+// there are no stream variables yet, and by flat_map before adding its own
+impl Clone for Expr {
+    fn clone(&self) -> Self {
+        match self {
+            Expr::Builtin(b, pos) => Expr::Builtin(b.clone(), *pos),
+            Expr::Literal(lit, pos) => Expr::Literal(lit.clone(), *pos),
+            Expr::UnresolvedIdentifier(idn) => Expr::UnresolvedIdentifier(idn.clone()),
+            Expr::FunCall(fcall) => Expr::FunCall(fcall.clone()),
+            Expr::Lambda(lambda) => Expr::Lambda(lambda.clone()),
+            Expr::Let(let_expr) => Expr::Let(let_expr.clone()),
+            Expr::Var(idn, var) => Expr::Var(idn.clone(), var.clone()),
+            Expr::ReadVar(..) => unreachable!("Stream variables can't be cloned"),
+        }
+    }
+}
+
 impl Position for Expr {
     fn position(&self) -> ParsePos {
         match self {
-            Expr::FunCall(fcall) =>
-                // TODO introduce a parse pos merging
-                fcall.function.position(),
+            Expr::FunCall(fcall) => {
+                // The whole call, from the function to the last argument
+                let fn_pos = fcall.function.position();
+                match fcall.arguments.last() {
+                    Some(last_arg) => fn_pos.merge(last_arg.position()),
+                    None => fn_pos,
+                }
+            }
             Expr::UnresolvedIdentifier(idn) =>
                 idn.position,
             Expr::Builtin(_, pos) =>
                 *pos,
-            _ =>
-                // FIXME this is terrible
-                todo!(),
+            Expr::Literal(_, pos) =>
+                *pos,
+            Expr::Lambda(lambda) =>
+                lambda.position.merge(lambda.body.position()),
+            Expr::Let(let_expr) =>
+                let_expr.position.merge(let_expr.body.position()),
+            Expr::Var(idn, _var) =>
+                idn.position,
+            Expr::ReadVar(_var, pos) =>
+                *pos,
         }
     }
 }
 
-/* Atoms */
-
-struct Atoms<'a> {
-    token_stream: token::Tokenizer<'a>
-}
-
-impl<'a> Iterator for Atoms<'a> {
-    type Item = Result<Expr, Error>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.token_stream
-            .next()
-            .map(|r| r.map(trivial_expr))
-    }
-}
+/* Parsing an expression tree */
 
 fn trivial_expr(token: Token) -> Expr {
     let pos = token.position;
@@ -106,82 +261,378 @@ fn trivial_expr(token: Token) -> Expr {
             Expr::Builtin(Builtin::RegexMatch(rm), pos),
         Kind::RegexSubst(subst) =>
             Expr::Builtin(Builtin::RegexSubst(subst), pos),
+        Kind::RegexCapture(rc) =>
+            Expr::Builtin(Builtin::RegexCapture(rc), pos),
+        Kind::StringLit(s) =>
+            Expr::Literal(Literal::String(s), pos),
+        Kind::IntLit(n) =>
+            Expr::Literal(Literal::Int(n), pos),
+        Kind::FloatLit(n) =>
+            Expr::Literal(Literal::Float(n), pos),
+        Kind::LeftParen | Kind::RightParen | Kind::LeftBracket | Kind::RightBracket |
+        Kind::Backslash | Kind::Arrow | Kind::Colon | Kind::Equals | Kind::Comma =>
+            // Punctuation is handled by the parser itself
+            unreachable!("Punctuation passed as a trivial expression"),
     }
 }
 
-/* Parsing an expression tree */
-
 fn build_exp_tree<I: Iterator<Item=Result<Token, Error>>>(token_stream: I) -> Result<Expr, Error> {
     let mut tokens =
         token_stream
             .inspect(
                 |t|
-                    match t {
-                        Ok(t) => eprintln!("next token: {:?}", t),
-                        _ => {},
+                    if let Ok(t) = t {
+                        eprintln!("next token: {:?}", t)
                     })
             .peekable();
 
     if tokens.peek().is_none() {
         return Err(Error::EmptyProgram);
     }
+
+    let final_tree = build_next_tree(&mut tokens)?;
+
+    match tokens.next() {
+        Some(Ok(Token { kind: token::Kind::RightParen, position })) =>
+            Err(Error::UnmatchedParen(position)),
+        Some(Ok(trailing)) =>
+            // The parser stopped on a token that can't start an expression (e.g. "in")
+            Err(Error::UnexpectedToken(trailing.position)),
+        Some(Err(e)) =>
+            // The tokenizer had an issue, just pass it along
+            Err(e),
+        None =>
+            // We reached the end of the stream (as expected)
+            Ok(final_tree),
+    }
+}
+
+// Parses the longest possible sequence of atoms, stopping at the end of the stream
+// or at a token that ends an expression (which is not consumed)
+// Several atoms in a row are considered a function call
+fn build_next_tree<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>) -> Result<Expr, Error> {
+    let first_atom = parse_atom(tokens)?;
+
+    let mut args = Vec::new();
+    while next_is_atom(tokens) {
+        let this_arg = parse_atom(tokens)?;
+        args.push(this_arg);
+    }
+
+    if args.is_empty() {
+        // No more atom: that's all we have
+        Ok(first_atom)
+    }
     else {
-        let final_tree = build_next_tree(&mut tokens)?;
+        Ok(FunCall::new_expr(first_atom, args))
+    }
+}
 
+fn next_is_atom<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>) -> bool {
+    match tokens.peek() {
+        None => false,
+        Some(Ok(Token { kind: token::Kind::RightParen, .. })) => false,
+        // Separators and end of a list literal
+        Some(Ok(Token { kind: token::Kind::Comma, .. })) => false,
+        Some(Ok(Token { kind: token::Kind::RightBracket, .. })) => false,
+        Some(Ok(Token { kind: token::Kind::Identifier(idn), .. })) => idn.name != "in",
+        // Let parse_atom report the error
+        Some(Err(_)) => true,
+        Some(Ok(_)) => true,
+    }
+}
+
+fn parse_atom<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>) -> Result<Expr, Error> {
+    let token =
         match tokens.next() {
-            Some(Ok(trailing)) =>
-                // We have more tokens, but we should have reached the end of the stream
-                // FIXME turn TooManyExprs into OrphanTokens
-                return Err(Error::TooManyExprs(trailing.position)),
-            Some(Err(e)) =>
-                // The tokenizer had an issue, just pass it along
-                return Err(e),
-            None =>
-                // We reached the end of the stream (as expected)
-                Ok(final_tree),
+            Some(token_res) => token_res?,
+            // The callers make sure that we don't get here with an empty stream,
+            // except for the empty program case which is handled separately
+            None => unreachable!("parse_atom called on an empty token stream"),
+        };
+
+    match token.kind {
+        token::Kind::LeftParen => {
+            let opening_pos = token.position;
+
+            if !next_is_atom(tokens) {
+                return Err(Error::ExpectedExpression(opening_pos.right_after()));
+            }
+            let inner = build_next_tree(tokens)?;
+
+            match tokens.next() {
+                Some(Ok(Token { kind: token::Kind::RightParen, .. })) =>
+                    Ok(inner),
+                Some(Err(e)) =>
+                    Err(e),
+                _ =>
+                    Err(Error::UnclosedParen(opening_pos)),
+            }
         }
+        token::Kind::RightParen =>
+            Err(Error::UnmatchedParen(token.position)),
+        token::Kind::LeftBracket =>
+            parse_list(token.position, tokens),
+        token::Kind::Backslash =>
+            parse_lambda(token.position, tokens),
+        token::Kind::Identifier(ref idn) if idn.name == "let" =>
+            parse_let(token.position, tokens),
+        token::Kind::Arrow | token::Kind::Colon | token::Kind::Equals | token::Kind::Comma |
+        token::Kind::RightBracket =>
+            Err(Error::UnexpectedToken(token.position)),
+        _ =>
+            Ok(trivial_expr(token)),
     }
 }
 
-// Pre-condition: the token stream has been peeked and is known to have at least one more token
-fn build_next_tree<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>) -> Result<Expr, Error> {
-    let first_token = tokens.next().unwrap()?;
-    let first_atom = trivial_expr(first_token);
+/// Retrieve the next token, which must exist.
+/// `prev_pos` is the position of the previous token, used to report a premature end of the program.
+fn expect_token<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>, expected: &'static str, prev_pos: ParsePos) -> Result<Token, Error> {
+    match tokens.next() {
+        Some(token_res) => token_res,
+        None => Err(Error::ExpectedToken { expected, err_pos: prev_pos.right_after() }),
+    }
+}
 
-    if tokens.peek().is_some() {
-        // There are more tokens: we assume a function call
-        parse_fun_call(first_atom, tokens)
+fn expect_kind<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>, kind: token::Kind, expected: &'static str, prev_pos: ParsePos) -> Result<ParsePos, Error> {
+    let token = expect_token(tokens, expected, prev_pos)?;
+    if std::mem::discriminant(&token.kind) == std::mem::discriminant(&kind) {
+        Ok(token.position)
     }
     else {
-        // No more token: that's all we have
-        Ok(first_atom)
+        Err(Error::ExpectedToken { expected, err_pos: token.position })
     }
 }
 
-fn parse_fun_call<I: Iterator<Item=Result<Token, Error>>>(first_token: Expr, rem_tokens: &mut Peekable<I>) -> Result<Expr, Error> {
-    let mut args = Vec::new();
+fn next_is<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>, kind: token::Kind) -> bool {
+    match tokens.peek() {
+        Some(Ok(token)) => std::mem::discriminant(&token.kind) == std::mem::discriminant(&kind),
+        _ => false,
+    }
+}
 
-    for token_res in rem_tokens {
-        let token = token_res?;
-        let this_arg = trivial_expr(token);
-        args.push(this_arg);
+// Syntax: [a, b, ...]
+// The opening bracket was already consumed. The list is desugared into a call to
+// the (unnamed) List builtin, with one argument per element
+fn parse_list<I: Iterator<Item=Result<Token, Error>>>(opening_pos: ParsePos, tokens: &mut Peekable<I>) -> Result<Expr, Error> {
+    let mut elements = Vec::new();
+    let mut prev_pos = opening_pos;
+    loop {
+        if !next_is_atom(tokens) {
+            return Err(Error::ExpectedExpression(prev_pos.right_after()));
+        }
+        let element = build_next_tree(tokens)?;
+        let element_pos = element.position();
+        elements.push(element);
+
+        let sep = expect_token(tokens, "\",\" or \"]\"", element_pos)?;
+        prev_pos = sep.position;
+        match sep.kind {
+            token::Kind::Comma => continue,
+            token::Kind::RightBracket => break,
+            _ => return Err(Error::ExpectedToken { expected: "\",\" or \"]\"", err_pos: sep.position }),
+        }
+    }
+
+    let list_pos = opening_pos.merge(prev_pos);
+    Ok(FunCall::new_expr(Expr::Builtin(Builtin::List, list_pos), elements))
+}
+
+// Syntax: \x y: int -> body
+// The body extends as far as possible
+fn parse_lambda<I: Iterator<Item=Result<Token, Error>>>(lambda_pos: ParsePos, tokens: &mut Peekable<I>) -> Result<Expr, Error> {
+    let mut params = Vec::new();
+    let mut prev_pos = lambda_pos;
+
+    loop {
+        let token = expect_token(tokens, "a parameter name or \"->\"", prev_pos)?;
+        prev_pos = token.position;
+
+        match token.kind {
+            token::Kind::Arrow if !params.is_empty() =>
+                break,
+            token::Kind::Identifier(idn) if !is_keyword(&idn.name) => {
+                let annotation = parse_opt_annotation(tokens)?;
+                params.push(Binding::new(idn, annotation));
+            }
+            _ =>
+                return Err(Error::ExpectedToken { expected: "a parameter name or \"->\"", err_pos: token.position }),
+        }
+    }
+
+    if !next_is_atom(tokens) {
+        return Err(Error::ExpectedExpression(prev_pos.right_after()));
+    }
+    let body = build_next_tree(tokens)?;
+
+    let lambda = Lambda { params, body: Box::new(body), position: lambda_pos };
+    Ok(Expr::Lambda(lambda))
+}
+
+// Syntax: let x: int = value in body
+fn parse_let<I: Iterator<Item=Result<Token, Error>>>(let_pos: ParsePos, tokens: &mut Peekable<I>) -> Result<Expr, Error> {
+    let name_token = expect_token(tokens, "a variable name", let_pos)?;
+    let name =
+        match name_token.kind {
+            token::Kind::Identifier(idn) if !is_keyword(&idn.name) => idn,
+            _ => return Err(Error::ExpectedToken { expected: "a variable name", err_pos: name_token.position }),
+        };
+
+    let annotation = parse_opt_annotation(tokens)?;
+    let prev_pos = annotation.as_ref().map(|a| a.position).unwrap_or(name.position);
+    let equals_pos = expect_kind(tokens, token::Kind::Equals, "\"=\"", prev_pos)?;
+
+    if !next_is_atom(tokens) {
+        return Err(Error::ExpectedExpression(equals_pos.right_after()));
+    }
+    let value = build_next_tree(tokens)?;
+
+    let in_pos =
+        match tokens.next() {
+            Some(Ok(Token { kind: token::Kind::Identifier(idn), .. })) if idn.name == "in" =>
+                idn.position,
+            Some(Ok(other)) =>
+                return Err(Error::ExpectedToken { expected: "\"in\"", err_pos: other.position }),
+            Some(Err(e)) =>
+                return Err(e),
+            None =>
+                return Err(Error::ExpectedToken { expected: "\"in\"", err_pos: value.position().right_after() }),
+        };
+
+    if !next_is_atom(tokens) {
+        return Err(Error::ExpectedExpression(in_pos.right_after()));
     }
+    let body = build_next_tree(tokens)?;
 
-    let fun_call = FunCall::new_expr(first_token, args);
-    Ok(fun_call)
+    let let_expr = Let {
+        binding:  Binding::new(name, annotation),
+        value:    Box::new(value),
+        body:     Box::new(body),
+        inline:   None,
+        position: let_pos,
+    };
+    Ok(Expr::Let(let_expr))
+}
+
+fn is_keyword(name: &str) -> bool {
+    matches!(name, "let" | "in")
+}
+
+fn parse_opt_annotation<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>) -> Result<Option<Annotation>, Error> {
+    if !next_is(tokens, token::Kind::Colon) {
+        return Ok(None);
+    }
+
+    let colon_pos = tokens.next().unwrap()?.position;
+    let (typ, type_pos) = parse_type(tokens, colon_pos)?;
+    Ok(Some(Annotation { typ, position: type_pos }))
+}
+
+// Syntax: the same as the one used to display types, e.g. "stream of maybe int"
+// Returns the position of the whole type
+fn parse_type<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>, prev_pos: ParsePos) -> Result<(Type, ParsePos), Error> {
+    let token = expect_token(tokens, "a type", prev_pos)?;
+    let start_pos = token.position;
+
+    let (typ, end_pos) =
+        match token.kind {
+            token::Kind::Identifier(idn) =>
+                match idn.name.as_str() {
+                    "string" => (Type::String, start_pos),
+                    "int"    => (Type::Int, start_pos),
+                    "float"  => (Type::Float, start_pos),
+                    "number" => (Type::Number, start_pos),
+                    "bool"   => (Type::Bool, start_pos),
+                    "stream" | "list" => {
+                        let of_pos = expect_of(tokens, start_pos)?;
+                        let (item_type, item_pos) = parse_type(tokens, of_pos)?;
+                        let typ =
+                            if idn.name == "stream" {
+                                Type::Stream(Box::new(item_type))
+                            }
+                            else {
+                                Type::List(Box::new(item_type))
+                            };
+                        (typ, item_pos)
+                    }
+                    "maybe" => {
+                        let (inner_type, inner_pos) = parse_type(tokens, start_pos)?;
+                        (Type::Maybe(Box::new(inner_type)), inner_pos)
+                    }
+                    "fn" => {
+                        let opening_pos = expect_kind(tokens, token::Kind::LeftParen, "\"(\"", start_pos)?;
+                        let (parameters, closing_pos) = parse_type_list(tokens, opening_pos)?;
+                        let arrow_pos = expect_kind(tokens, token::Kind::Arrow, "\"->\"", closing_pos)?;
+                        let (return_type, return_pos) = parse_type(tokens, arrow_pos)?;
+                        (Type::Function { parameters, return_type: Box::new(return_type) }, return_pos)
+                    }
+                    _ =>
+                        return Err(Error::UnknownType(start_pos)),
+                }
+            token::Kind::LeftParen => {
+                // Either a parenthesized type or a tuple
+                let (mut types, closing_pos) = parse_type_list(tokens, start_pos)?;
+                if types.len() == 1 {
+                    (types.pop().unwrap(), closing_pos)
+                }
+                else {
+                    (Type::Tuple(types), closing_pos)
+                }
+            }
+            _ =>
+                return Err(Error::ExpectedToken { expected: "a type", err_pos: start_pos }),
+        };
+
+    Ok((typ, start_pos.merge(end_pos)))
+}
+
+// Syntax: T, U, ...)
+// The opening parenthesis was already consumed
+fn parse_type_list<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>, opening_pos: ParsePos) -> Result<(Vec<Type>, ParsePos), Error> {
+    let mut types = Vec::new();
+    let mut prev_pos = opening_pos;
+    loop {
+        let (typ, type_pos) = parse_type(tokens, prev_pos)?;
+        types.push(typ);
+
+        let sep = expect_token(tokens, "\",\" or \")\"", type_pos)?;
+        prev_pos = sep.position;
+        match sep.kind {
+            token::Kind::Comma => continue,
+            token::Kind::RightParen => return Ok((types, prev_pos)),
+            _ => return Err(Error::ExpectedToken { expected: "\",\" or \")\"", err_pos: sep.position }),
+        }
+    }
+}
+
+fn expect_of<I: Iterator<Item=Result<Token, Error>>>(tokens: &mut Peekable<I>, prev_pos: ParsePos) -> Result<ParsePos, Error> {
+    let token = expect_token(tokens, "\"of\"", prev_pos)?;
+    match token.kind {
+        token::Kind::Identifier(idn) if idn.name == "of" => Ok(token.position),
+        _ => Err(Error::ExpectedToken { expected: "\"of\"", err_pos: token.position }),
+    }
 }
 
 /* FunCall */
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FunCall {
     pub function:  Box<Expr>,
     pub arguments: Vec<Expr>,
 }
 
 impl FunCall {
+    /// Note: calling the result of a partial application is the same thing as
+    /// calling the function with all the arguments at once. We flatten such calls
+    /// so that later phases only ever see the innermost function.
     pub fn new_expr(function: Expr, arguments: Vec<Expr>) -> Expr {
-        Self::new_expr_boxed(Box::new(function), arguments)
+        match function {
+            Expr::FunCall(mut partial) => {
+                partial.arguments.extend(arguments);
+                Expr::FunCall(partial)
+            }
+            _ => Self::new_expr_boxed(Box::new(function), arguments),
+        }
     }
 
     fn new_expr_boxed(function: Box<Expr>, arguments: Vec<Expr>) -> Expr {
@@ -190,37 +641,280 @@ impl FunCall {
     }
 }
 
+/* Lambdas and let bindings */
+
+#[derive(Debug, Clone)]
+pub struct Lambda {
+    pub params:   Vec<Binding>,
+    pub body:     Box<Expr>,
+    pub position: ParsePos,
+}
+
+#[derive(Debug, Clone)]
+pub struct Let {
+    pub binding:  Binding,
+    pub value:    Box<Expr>,
+    pub body:     Box<Expr>,
+    /// Set during typechecking for bindings that must be inlined
+    pub inline:   Option<Inlining>,
+    pub position: ParsePos,
+}
+
+/// Streams and functions are not runtime values: variables bound to them
+/// get substituted with their value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inlining {
+    Function,
+    // A stream can only be consumed once
+    Stream,
+}
+
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name:       Identifier,
+    pub annotation: Option<Annotation>,
+    pub var:        Variable,
+}
+
+/// A user-provided type annotation
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    pub typ:      Type,
+    pub position: ParsePos,
+}
+
+/// A variable introduced by a binding.
+/// The id identifies it during compilation, the local variable holds its runtime value.
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub id:    usize,
+    pub value: runtime::LocalVar,
+}
+
+impl Binding {
+    fn new(name: Identifier, annotation: Option<Annotation>) -> Self {
+        Binding { name, annotation, var: Variable::fresh() }
+    }
+}
+
+impl Variable {
+    pub fn fresh() -> Self {
+        static NEXT_VAR_ID: AtomicUsize = AtomicUsize::new(0);
+
+        Variable {
+            id:    NEXT_VAR_ID.fetch_add(1, Ordering::Relaxed),
+            value: runtime::LocalVar::new(),
+        }
+    }
+}
+
 /* Name resolution */
 
-fn name_resolution(expr_tree: &mut Expr) -> Result<(), Error> {
+type Scope = Vec<(String, Variable)>;
+
+fn name_resolution(expr_tree: &mut Expr, scope: &mut Scope) -> Result<(), Error> {
     match expr_tree {
         Expr::UnresolvedIdentifier(idn) => {
-            let pos = idn.position;
-            let builtin = resolve_builtin(idn.take())?;
-            *expr_tree = Expr::Builtin(builtin, pos);
+            // Variables shadow builtins (and outer variables)
+            let in_scope =
+                scope.iter()
+                    .rev()
+                    .find(|(name, _var)| *name == idn.name)
+                    .map(|(_name, var)| var.clone());
+
+            match in_scope {
+                Some(var) => {
+                    *expr_tree = Expr::Var(idn.take(), var);
+                }
+                None => {
+                    let pos = idn.position;
+                    let builtin = resolve_builtin(idn.take(), scope)?;
+                    *expr_tree = Expr::Builtin(builtin, pos);
+                }
+            }
+            Ok(())
+        }
+
+        Expr::Lambda(lambda) => {
+            let scope_len = scope.len();
+            for param in &lambda.params {
+                scope.push((param.name.name.clone(), param.var.clone()));
+            }
+            name_resolution(&mut lambda.body, scope)?;
+            scope.truncate(scope_len);
+            Ok(())
+        }
+
+        Expr::Let(let_expr) => {
+            // Note: the bound variable is not visible in its own value
+            name_resolution(&mut let_expr.value, scope)?;
+            scope.push((let_expr.binding.name.name.clone(), let_expr.binding.var.clone()));
+            name_resolution(&mut let_expr.body, scope)?;
+            scope.pop();
+            Ok(())
+        }
+
+        _ => {
+            // Now resolve the children
+            for subtree in expr_tree.children_mut() {
+                name_resolution(subtree, scope)?;
+            }
+            Ok(())
         }
-        _ => {},
     }
+}
 
-    // Now resolve the children
-    for subtree in expr_tree.children_mut() {
-        name_resolution(subtree)?;
+type BuildBuiltin = fn() -> Builtin;
+
+// Note: the lookup is linear, which is fine for such a small table
+const BUILTINS: [(&str, BuildBuiltin); 84] = [
+    ("stdin",          || Builtin::Stdin),
+    ("file",           || Builtin::File),
+    ("concat",         || Builtin::Concat),
+    ("zip",            || Builtin::Zip),
+    ("interleave",     || Builtin::Interleave),
+    ("filter",         || Builtin::Filter),
+    ("between",        || Builtin::Between(RangeMode::Inclusive)),
+    ("between_excl",   || Builtin::Between(RangeMode::Exclusive)),
+    ("between_once",   || Builtin::Between(RangeMode::Once)),
+    ("context",        || Builtin::Context),
+    ("inner_join",     || Builtin::HashJoin(JoinMode::Inner)),
+    ("left_join",      || Builtin::HashJoin(JoinMode::Left)),
+    ("anti_join",      || Builtin::HashJoin(JoinMode::Anti)),
+    ("map",            || Builtin::Map),
+    ("flatten",        || Builtin::Flatten),
+    ("flat_map",       || Builtin::FlatMap { stream: false }),
+    ("split_lines",    || Builtin::SplitLines),
+    ("filter_some",    || Builtin::FilterSome),
+    ("take",           || Builtin::Take),
+    ("head",           || Builtin::Take),
+    ("drop",           || Builtin::Drop),
+    ("skip",           || Builtin::Drop),
+    ("take_while",     || Builtin::TakeWhile),
+    ("drop_while",     || Builtin::DropWhile),
+    ("tail",           || Builtin::Tail),
+    ("tail_after",     || Builtin::TailAfter),
+    ("sample",         || Builtin::Sample),
+    ("every",          || Builtin::Every),
+    ("bernoulli",      || Builtin::Bernoulli),
+    ("uniq",           || Builtin::Uniq),
+    ("uniq_c",         || Builtin::UniqCount),
+    ("distinct",       || Builtin::Distinct),
+    ("distinct_by",    || Builtin::DistinctBy),
+    ("sort",           || Builtin::Sort),
+    ("sort_by",        || Builtin::SortBy),
+    ("rsort",          || Builtin::ReverseSort),
+    ("rsort_by",       || Builtin::ReverseSortBy),
+    ("group_by",       || Builtin::GroupBy(GroupOrder::FirstSeen)),
+    ("group_by_key",   || Builtin::GroupBy(GroupOrder::Key)),
+    ("group_by_value", || Builtin::GroupBy(GroupOrder::Value)),
+    ("bucket_by_time", || Builtin::BucketByTime),
+    ("bucket_fill",    || Builtin::BucketFill),
+    ("top",            || Builtin::Top),
+    ("frequencies",    || Builtin::Frequencies),
+    ("frequent",       || Builtin::Frequent),
+    ("scan",           || Builtin::Scan),
+    ("enumerate",      || Builtin::Enumerate),
+    ("lines",          || Builtin::Lines),
+    ("window",         || Builtin::Window),
+    ("chunks",         || Builtin::Chunks),
+    ("moving_avg",     || Builtin::MovingAvg),
+    ("histogram",      || Builtin::Histogram),
+    ("log_hist",       || Builtin::LogHistogram),
+    ("bars",           || Builtin::Bars),
+    ("quantiles",      || Builtin::Quantiles),
+    ("num",            || Builtin::ToNumber),
+    ("num?",           || Builtin::TryToNumber),
+    ("int",            || Builtin::ToInt),
+    ("int?",           || Builtin::TryToInt),
+    ("float",          || Builtin::ToFloat),
+    ("float?",         || Builtin::TryToFloat),
+    ("add",            || Builtin::Arith(ArithOp::Add)),
+    ("sub",            || Builtin::Arith(ArithOp::Sub)),
+    ("mul",            || Builtin::Arith(ArithOp::Mul)),
+    ("div",            || Builtin::Arith(ArithOp::Div)),
+    ("mod",            || Builtin::Arith(ArithOp::Mod)),
+    ("lineno",         || Builtin::Lineno),
+    ("split",          || Builtin::Split),
+    ("words",          || Builtin::Words),
+    ("at",             || Builtin::At),
+    ("at?",            || Builtin::TryAt),
+    ("len",            || Builtin::Len),
+    ("join",           || Builtin::Join),
+    ("default",        || Builtin::Default),
+    ("is_some",        || Builtin::IsSome),
+    ("fst",            || Builtin::First),
+    ("snd",            || Builtin::Second),
+    ("count",          || Builtin::Reduce(ReduceOp::Count)),
+    ("sum",            || Builtin::Reduce(ReduceOp::Sum { float: false })),
+    ("min",            || Builtin::Reduce(ReduceOp::Min)),
+    ("max",            || Builtin::Reduce(ReduceOp::Max)),
+    ("avg",            || Builtin::Reduce(ReduceOp::Avg)),
+    ("fold",           || Builtin::Fold),
+    ("percentile",     || Builtin::Percentile),
+];
+
+fn resolve_builtin(starting_idn: Identifier, scope: &Scope) -> Result<Builtin, Error> {
+    let found =
+        BUILTINS.iter()
+            .find(|(name, _build)| *name == starting_idn.name)
+            .map(|(_name, build)| build());
+
+    match found {
+        Some(builtin) => Ok(builtin),
+        None => {
+            // Both the builtins and the variables in scope are candidates
+            let candidates =
+                BUILTINS.iter()
+                    .map(|(name, _build)| *name)
+                    .chain(scope.iter().map(|(name, _var)| name.as_str()));
+            let suggestion = closest_name(&starting_idn.name, candidates);
+            Err(Error::CantResolve { idn: starting_idn, suggestion })
+        }
     }
+}
+
+/// The candidate closest to the given name, if any is close enough to be a plausible typo
+fn closest_name<'a, I: Iterator<Item=&'a str>>(name: &str, candidates: I) -> Option<String> {
+    // Allow one edit for short names, and roughly one more per three characters
+    let max_distance = 1 + name.chars().count() / 3;
 
-    Ok(())
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _candidate)| *distance <= max_distance)
+        // Note: min_by_key returns the first candidate in case of a tie
+        .min_by_key(|(distance, _candidate)| *distance)
+        .map(|(_distance, candidate)| candidate.to_string())
 }
 
-fn resolve_builtin(starting_idn: Identifier) -> Result<Builtin, Error> {
-    // Note: Rust playground's MIR emit shows us that a constant string
-    // match is basically turned into cascading if-elses. For performance,
-    // we would want to use a constant hash map here.
-    match starting_idn.name.as_str() {
-        "stdin"  => Ok(Builtin::Stdin),
-        "filter" => Ok(Builtin::Filter),
-        "map"    => Ok(Builtin::Map),
-        "num"    => Ok(Builtin::ToNumber),
-        _        => Err(Error::CantResolve(starting_idn)),
+/// Levenshtein distance, with transpositions of adjacent characters counting as a single edit
+fn edit_distance(left: &str, right: &str) -> usize {
+    let left: Vec<char> = left.chars().collect();
+    let right: Vec<char> = right.chars().collect();
+
+    // dist[i][j] is the distance between the first i chars of left and the first j chars of right
+    let mut dist = vec![vec![0; right.len() + 1]; left.len() + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    dist[0] = (0..=right.len()).collect();
+
+    for i in 1..=left.len() {
+        for j in 1..=right.len() {
+            let subst_cost = if left[i - 1] == right[j - 1] { 0 } else { 1 };
+            let mut best =
+                (dist[i - 1][j] + 1)
+                    .min(dist[i][j - 1] + 1)
+                    .min(dist[i - 1][j - 1] + subst_cost);
+
+            if i > 1 && j > 1 && left[i - 1] == right[j - 2] && left[i - 2] == right[j - 1] {
+                best = best.min(dist[i - 2][j - 2] + 1);
+            }
+            dist[i][j] = best;
+        }
     }
+
+    dist[left.len()][right.len()]
 }
 
 /* Pretty printing */
@@ -230,17 +924,43 @@ impl Display for Expr {
         match self {
             Expr::Builtin(b, _pos) =>
                 write!(f, "{}", b),
+            Expr::Literal(lit, _pos) =>
+                write!(f, "{}", lit),
             Expr::UnresolvedIdentifier(identifier) => {
                 write!(f, "?:{}:?", identifier.name)
             },
+            Expr::FunCall(fcall) if matches!(*fcall.function, Expr::Builtin(Builtin::List, _)) => {
+                write!(f, "[")?;
+                for (idx, elem) in fcall.arguments.iter().enumerate() {
+                    let sep = if idx == 0 { "" } else { ", " };
+                    write!(f, "{}{}", sep, elem)?;
+                }
+                write!(f, "]")
+            },
             Expr::FunCall(fcall) => {
                 write!(f, "{}", fcall.function)?;
                 for arg in &fcall.arguments {
-                    write!(f, " {}", arg)?;
+                    match arg {
+                        Expr::FunCall(_) => write!(f, " ({})", arg)?,
+                        _ => write!(f, " {}", arg)?,
+                    }
                 }
                 Ok(())
             },
-            Expr::ReadVar(stream_var) => {
+            Expr::Lambda(lambda) => {
+                write!(f, "(\\")?;
+                for param in &lambda.params {
+                    write!(f, "{}", param)?;
+                }
+                write!(f, " -> {})", lambda.body)
+            },
+            Expr::Let(let_expr) => {
+                write!(f, "(let{} = {} in {})", let_expr.binding, let_expr.value, let_expr.body)
+            },
+            Expr::Var(idn, _var) => {
+                write!(f, "{}", idn.name)
+            },
+            Expr::ReadVar(stream_var, _pos) => {
                 write!(f, "(read {:?})", stream_var)
             },
         }
@@ -252,16 +972,204 @@ impl Display for Builtin {
         match self {
             Builtin::Stdin =>
                 write!(f, "stdin"),
+            Builtin::File =>
+                write!(f, "file"),
+            Builtin::Concat =>
+                write!(f, "concat"),
+            Builtin::Zip =>
+                write!(f, "zip"),
+            Builtin::Interleave =>
+                write!(f, "interleave"),
             Builtin::RegexMatch(re) =>
                 write!(f, "m/{}/", re.as_str()),
             Builtin::RegexSubst(subst) =>
                 write!(f, "s/{}/{}/", subst.search.as_str(), subst.replace),
+            Builtin::RegexCapture(re) =>
+                write!(f, "c/{}/", re.as_str()),
             Builtin::Filter =>
                 write!(f, "filter"),
+            Builtin::Between(RangeMode::Inclusive) =>
+                write!(f, "between"),
+            Builtin::Between(RangeMode::Exclusive) =>
+                write!(f, "between_excl"),
+            Builtin::Between(RangeMode::Once) =>
+                write!(f, "between_once"),
+            Builtin::Context =>
+                write!(f, "context"),
+            Builtin::HashJoin(JoinMode::Inner) =>
+                write!(f, "inner_join"),
+            Builtin::HashJoin(JoinMode::Left) =>
+                write!(f, "left_join"),
+            Builtin::HashJoin(JoinMode::Anti) =>
+                write!(f, "anti_join"),
             Builtin::Map =>
                 write!(f, "map"),
             Builtin::ToNumber =>
                 write!(f, "num"),
+            Builtin::TryToNumber =>
+                write!(f, "num?"),
+            Builtin::ToInt =>
+                write!(f, "int"),
+            Builtin::TryToInt =>
+                write!(f, "int?"),
+            Builtin::ToFloat =>
+                write!(f, "float"),
+            Builtin::TryToFloat =>
+                write!(f, "float?"),
+            Builtin::Lineno =>
+                write!(f, "lineno"),
+            Builtin::Arith(op) =>
+                write!(f, "{}", op),
+            Builtin::Split =>
+                write!(f, "split"),
+            Builtin::Words =>
+                write!(f, "words"),
+            Builtin::At =>
+                write!(f, "at"),
+            Builtin::TryAt =>
+                write!(f, "at?"),
+            Builtin::Len =>
+                write!(f, "len"),
+            Builtin::Join =>
+                write!(f, "join"),
+            Builtin::Flatten =>
+                write!(f, "flatten"),
+            Builtin::FlatMap { .. } =>
+                write!(f, "flat_map"),
+            Builtin::SplitLines =>
+                write!(f, "split_lines"),
+            Builtin::FilterSome =>
+                write!(f, "filter_some"),
+            Builtin::Take =>
+                write!(f, "take"),
+            Builtin::Drop =>
+                write!(f, "drop"),
+            Builtin::TakeWhile =>
+                write!(f, "take_while"),
+            Builtin::DropWhile =>
+                write!(f, "drop_while"),
+            Builtin::Tail =>
+                write!(f, "tail"),
+            Builtin::TailAfter =>
+                write!(f, "tail_after"),
+            Builtin::Uniq =>
+                write!(f, "uniq"),
+            Builtin::UniqCount =>
+                write!(f, "uniq_c"),
+            Builtin::Distinct =>
+                write!(f, "distinct"),
+            Builtin::DistinctBy =>
+                write!(f, "distinct_by"),
+            Builtin::Sort =>
+                write!(f, "sort"),
+            Builtin::SortBy =>
+                write!(f, "sort_by"),
+            Builtin::ReverseSort =>
+                write!(f, "rsort"),
+            Builtin::ReverseSortBy =>
+                write!(f, "rsort_by"),
+            Builtin::GroupBy(GroupOrder::FirstSeen) =>
+                write!(f, "group_by"),
+            Builtin::GroupBy(GroupOrder::Key) =>
+                write!(f, "group_by_key"),
+            Builtin::GroupBy(GroupOrder::Value) =>
+                write!(f, "group_by_value"),
+            Builtin::Top =>
+                write!(f, "top"),
+            Builtin::Frequencies =>
+                write!(f, "frequencies"),
+            Builtin::Frequent =>
+                write!(f, "frequent"),
+            Builtin::Scan =>
+                write!(f, "scan"),
+            Builtin::Enumerate =>
+                write!(f, "enumerate"),
+            Builtin::Lines =>
+                write!(f, "lines"),
+            Builtin::Window =>
+                write!(f, "window"),
+            Builtin::Chunks =>
+                write!(f, "chunks"),
+            Builtin::MovingAvg =>
+                write!(f, "moving_avg"),
+            Builtin::Histogram =>
+                write!(f, "histogram"),
+            Builtin::LogHistogram =>
+                write!(f, "log_hist"),
+            Builtin::Bars =>
+                write!(f, "bars"),
+            Builtin::Quantiles =>
+                write!(f, "quantiles"),
+            Builtin::Sample =>
+                write!(f, "sample"),
+            Builtin::BucketByTime =>
+                write!(f, "bucket_by_time"),
+            Builtin::BucketFill =>
+                write!(f, "bucket_fill"),
+            Builtin::Every =>
+                write!(f, "every"),
+            Builtin::Bernoulli =>
+                write!(f, "bernoulli"),
+            Builtin::Default =>
+                write!(f, "default"),
+            Builtin::IsSome =>
+                write!(f, "is_some"),
+            Builtin::First =>
+                write!(f, "fst"),
+            Builtin::Second =>
+                write!(f, "snd"),
+            Builtin::Reduce(op) =>
+                write!(f, "{}", op),
+            Builtin::Fold =>
+                write!(f, "fold"),
+            Builtin::Percentile =>
+                write!(f, "percentile"),
+            Builtin::List =>
+                write!(f, "list"),
+        }
+    }
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, " {}", self.name.name)?;
+        if let Some(annotation) = &self.annotation {
+            write!(f, ": {}", annotation.typ)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::String(s) => write!(f, "{:?}", s),
+            Literal::Int(n) => write!(f, "{}", n),
+            Literal::Float(n) => write!(f, "{:?}", n),
+        }
+    }
+}
+
+impl Display for ReduceOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReduceOp::Count => write!(f, "count"),
+            ReduceOp::Sum { .. } => write!(f, "sum"),
+            ReduceOp::Min => write!(f, "min"),
+            ReduceOp::Max => write!(f, "max"),
+            ReduceOp::Avg => write!(f, "avg"),
+        }
+    }
+}
+
+impl Display for ArithOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArithOp::Add => write!(f, "add"),
+            ArithOp::Sub => write!(f, "sub"),
+            ArithOp::Mul => write!(f, "mul"),
+            ArithOp::Div => write!(f, "div"),
+            ArithOp::Mod => write!(f, "mod"),
         }
     }
 }
\ No newline at end of file
@@ -28,6 +28,19 @@ pub enum Kind {
     Identifier(Identifier),
     RegexMatch(Regex),
     RegexSubst(RegexSubst),
+    RegexCapture(Regex),
+    StringLit(String),
+    IntLit(i64),
+    FloatLit(f64),
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    Arrow,
+    Colon,
+    Equals,
+    Comma,
 }
 
 impl Token {
@@ -48,6 +61,19 @@ impl Debug for Kind {
             Kind::Identifier(idn) => write!(f, "Identifier({:?})", idn.name),
             Kind::RegexMatch(re) => write!(f, "RegexMatch({:?})", re.as_str()),
             Kind::RegexSubst(subst) => write!(f, "RegexSubst({:?} -> {:?})", subst.search.as_str(), subst.replace),
+            Kind::RegexCapture(re) => write!(f, "RegexCapture({:?})", re.as_str()),
+            Kind::StringLit(s) => write!(f, "StringLit({:?})", s),
+            Kind::IntLit(n) => write!(f, "IntLit({})", n),
+            Kind::FloatLit(n) => write!(f, "FloatLit({})", n),
+            Kind::LeftParen => write!(f, "LeftParen"),
+            Kind::RightParen => write!(f, "RightParen"),
+            Kind::LeftBracket => write!(f, "LeftBracket"),
+            Kind::RightBracket => write!(f, "RightBracket"),
+            Kind::Backslash => write!(f, "Backslash"),
+            Kind::Arrow => write!(f, "Arrow"),
+            Kind::Colon => write!(f, "Colon"),
+            Kind::Equals => write!(f, "Equals"),
+            Kind::Comma => write!(f, "Comma"),
         }
     }
 }
@@ -88,18 +114,12 @@ impl<'a> Tokenizer<'a> {
 
     fn trim_leading_whitespaces(&mut self) {
         // TODO use a constant regex
+        // Note: there may be no whitespace at all between two tokens, e.g. "(stdin)"
         let rem_source = &self.source[self.curr_pos..];
         let rem_no_ws = rem_source.trim_start();
         let len_diff = rem_source.len() - rem_no_ws.len();
 
-        if len_diff == 0 {
-            // Considering how the tokenizer works, this can never happen before the end of the source program
-            assert!(self.at_end() || self.curr_pos == 0,
-                "No trimmed spaces at position {}, i.e. {:?}", self.curr_pos, &self.source[self.curr_pos..]);
-        }
-        else {
-            self.curr_pos += len_diff;
-        }
+        self.curr_pos += len_diff;
     }
 }
 
@@ -123,11 +143,18 @@ impl From<&TRDef> for TokenRx {
     }
 }
 
-const TOKEN_RXS: [TRDef; 3] = [
+const TOKEN_RXS: [TRDef; 8] = [
     // WARNING the ordering matters here
-    ("m/((?:[^/]|\\/)*)/",   regex_match),
-    ("s/((?:[^/]|\\/)*)/((?:[^/]|\\/)*)/",   RegexSubst::token),
-    ("[a-zA-Z][0-9a-zA-Z]*", Identifier::token),
+    // Note: slashes can be escaped inside regexes, e.g. m/a\/b/
+    ("m/((?:[^/\\\\]|\\\\.)*)/",   regex_match),
+    ("s/((?:[^/\\\\]|\\\\.)*)/((?:[^/\\\\]|\\\\.)*)/",   RegexSubst::token),
+    ("c/((?:[^/\\\\]|\\\\.)*)/",   regex_capture),
+    ("\"((?:[^\"\\\\]|\\\\.)*)\"", string_lit),
+    ("->",                   punctuation),
+    ("-?[0-9]+(\\.[0-9]+)?([eE][-+]?[0-9]+)?", number_lit),
+    ("[()\\[\\]\\\\:=,]",      punctuation),
+    // Note: a trailing question mark denotes the "try" variant of a builtin
+    ("[a-zA-Z_][0-9a-zA-Z_]*\\??", Identifier::token),
 ];
 
 fn regex_match(rec: &regex::Captures) -> Token {
@@ -141,9 +168,76 @@ fn regex_match(rec: &regex::Captures) -> Token {
     Token { position: pos, kind: Kind::RegexMatch(re_match) }
 }
 
+fn regex_capture(rec: &regex::Captures) -> Token {
+    let m = rec.get(1).unwrap();
+    // FIXME need to return a proper error here
+    let re_capture = Regex::new(m.as_str()).unwrap();
+
+    let pos = ParsePos::from_captures(rec);
+    Token { position: pos, kind: Kind::RegexCapture(re_capture) }
+}
+
+fn string_lit(rec: &regex::Captures) -> Token {
+    let escaped = rec.get(1).unwrap().as_str();
+
+    let mut value = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            // The token regex guarantees that a backslash is always followed by another character
+            match chars.next().unwrap() {
+                'n'   => value.push('\n'),
+                't'   => value.push('\t'),
+                other => value.push(other),
+            }
+        }
+        else {
+            value.push(c);
+        }
+    }
+
+    let pos = ParsePos::from_captures(rec);
+    Token { position: pos, kind: Kind::StringLit(value) }
+}
+
+fn number_lit(rec: &regex::Captures) -> Token {
+    let literal = rec.get(0).unwrap().as_str();
+    let is_float = rec.get(1).is_some() || rec.get(2).is_some();
+
+    // The token regex only accepts valid number literals,
+    // but integer literals may still be too big for an int
+    let kind =
+        match literal.parse() {
+            Ok(int_value) if !is_float => Kind::IntLit(int_value),
+            _ => Kind::FloatLit(literal.parse().unwrap()),
+        };
+
+    let pos = ParsePos::from_captures(rec);
+    Token { position: pos, kind }
+}
+
+fn punctuation(rec: &regex::Captures) -> Token {
+    let kind =
+        match rec.get(0).unwrap().as_str() {
+            "("  => Kind::LeftParen,
+            ")"  => Kind::RightParen,
+            "["  => Kind::LeftBracket,
+            "]"  => Kind::RightBracket,
+            "\\" => Kind::Backslash,
+            "->" => Kind::Arrow,
+            ":"  => Kind::Colon,
+            "="  => Kind::Equals,
+            ","  => Kind::Comma,
+            other => unreachable!("Unexpected punctuation {:?}", other),
+        };
+
+    let pos = ParsePos::from_captures(rec);
+    Token { position: pos, kind }
+}
+
 /* Identifier */
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Identifier {
     pub name:     String,
     pub position: ParsePos
@@ -166,7 +260,7 @@ impl Identifier {
     /// semantically invalid state.
     pub fn take(&mut self) -> Self {
         // Note: the docs tell us that String::new() does not lead to an allocation
-        let name = std::mem::replace(&mut self.name, String::new());
+        let name = std::mem::take(&mut self.name);
         let position = self.position;
         Self { name, position }
     }
@@ -174,7 +268,7 @@ impl Identifier {
 
 /* RegexSubst */
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RegexSubst {
     pub search:  Regex,
     pub replace: String,
@@ -191,11 +285,12 @@ impl RegexSubst {
         // What are the cases when this can fail though? Unmatched parentheses maybe?
         let search_re = Regex::new(search_str).unwrap();
 
+        // Unlike in the search regex, an escaped slash has to be unescaped here
         let replace_str =
             rec.get(2)
                 .unwrap()
                 .as_str()
-                .into();
+                .replace("\\/", "/");
 
         let pos = ParsePos::from_captures(rec);
 
@@ -243,4 +338,11 @@ impl ParsePos {
     pub fn right_after(&self) -> ParsePos {
         ParsePos { start: self.start + self.len, len: 1 }
     }
+
+    /// The smallest position covering both self and other
+    pub fn merge(&self, other: ParsePos) -> ParsePos {
+        let start = self.start.min(other.start);
+        let end = (self.start + self.len).max(other.start + other.len);
+        ParsePos { start, len: end - start }
+    }
 }
\ No newline at end of file
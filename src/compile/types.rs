@@ -1,39 +1,67 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display};
 
 use crate::Error;
 
-use super::{Builtin, Expr, FunCall, Position};
+use super::{parse::{Binding, Inlining, Lambda, Let}, Builtin, Expr, FunCall, JoinMode, Literal, ParsePos, Position, ReduceOp};
 
 /// Type checks an expression tree as a full program
-/// The top-level type is guaranteed to be formattable
-pub fn typecheck_program(program: &mut Expr) -> Result<(), Error> {
-    let top_level_type = program.typecheck()?;
+/// The top-level type is guaranteed to be formattable.
+/// Returns whether the program outputs a stream (as opposed to a single value).
+pub fn typecheck_program(program: &mut Expr) -> Result<bool, Error> {
+    let top_level_type = typecheck_expr(program)?;
+
+    if let (Type::Function { parameters, .. }, Expr::FunCall(fcall)) = (&top_level_type, &*program) {
+        // This is a partial application: report the missing arguments
+        let found = fcall.arguments.len();
+        return Err(Error::NotEnoughArguments {
+            expected: found + parameters.len(),
+            found,
+            err_pos:  fcall.arguments.last().unwrap().position().right_after()
+        });
+    }
 
     if !is_formattable(&top_level_type) {
         Err(Error::NonFormattable(format!("{}", top_level_type)))
     }
     else {
-        Ok(())
+        Ok(top_level_type.stream_item().is_some())
     }
 }
 
+/// Type checks an expression tree, whatever its type
+pub fn typecheck_expr(expr: &mut Expr) -> Result<Type, Error> {
+    expr.typecheck(&mut TypeEnv::default())
+}
+
 fn is_formattable(typ: &Type) -> bool {
-    // For now, only streams of a base type are formattable
-    match typ.stream_item() {
-        Some(Type::String) => true,
-        Some(Type::Number) => true,
-        Some(Type::Bool)   => true,
-        _                  => false,
+    // For now, only (streams of) possibly optional base types, or tuples of them, are formattable
+    match typ.stream_item().unwrap_or(typ) {
+        Type::Tuple(elems) => elems.iter().all(is_formattable_scalar),
+        item => is_formattable_scalar(item),
+    }
+}
+
+fn is_formattable_scalar(typ: &Type) -> bool {
+    match typ {
+        Type::Maybe(inner) => inner.is_base(),
+        _ => typ.is_base(),
     }
 }
 
 /* Type */
 
-#[derive(Clone, PartialEq, Eq)]
-enum Type {
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Type {
     String,
+    Int,
+    Float,
+    // Either an int or a float, as decided at runtime
     Number,
     Bool,
+    List(Box<Type>),
+    Maybe(Box<Type>),
+    // At least two elements
+    Tuple(Vec<Type>),
     Stream(Box<Type>),
     Function { parameters: Vec<Type>, return_type: Box<Type> },
 }
@@ -43,6 +71,14 @@ impl Type {
         Self::Stream(Box::new(of_what))
     }
 
+    fn list(of_what: Type) -> Self {
+        Self::List(Box::new(of_what))
+    }
+
+    fn maybe(of_what: Type) -> Self {
+        Self::Maybe(Box::new(of_what))
+    }
+
     fn function(parameters: Vec<Type>, return_type: Type) -> Self {
         Self::Function { parameters, return_type: Box::new(return_type) }
     }
@@ -53,19 +89,73 @@ impl Type {
             _ => None,
         }
     }
+
+    fn is_base(&self) -> bool {
+        matches!(self, Type::String | Type::Bool) || self.is_numeric()
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Float | Type::Number)
+    }
+
+    /// Whether a value of type `other` can be used where `self` is expected
+    fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            // Any numeric value can be used where a number is expected
+            (Type::Number, _) => other.is_numeric(),
+
+            (Type::List(expected), Type::List(found)) => expected.accepts(found),
+            (Type::Maybe(expected), Type::Maybe(found)) => expected.accepts(found),
+            (Type::Stream(expected), Type::Stream(found)) => expected.accepts(found),
+            (Type::Tuple(expected), Type::Tuple(found)) =>
+                expected.len() == found.len()
+                    && expected.iter()
+                        .zip(found)
+                        .all(|(expected, found)| expected.accepts(found)),
+
+            (Type::Function { parameters: expected_params, return_type: expected_ret },
+             Type::Function { parameters: found_params, return_type: found_ret }) =>
+                expected_params.len() == found_params.len()
+                    && expected_params.iter()
+                        .zip(found_params)
+                        .all(|(expected, found)| found.accepts(expected))
+                    && expected_ret.accepts(found_ret),
+
+            _ => self == other,
+        }
+    }
+
+    /// Type of the result of an arithmetic operation between numeric values of the given types
+    /// Ints are promoted to floats if required.
+    fn promote(left: &Type, right: &Type) -> Type {
+        match (left, right) {
+            (Type::Int, Type::Int) => Type::Int,
+            (Type::Number, _) | (_, Type::Number) => Type::Number,
+            _ => Type::Float,
+        }
+    }
 }
 
 /* Typecheck trait and logic */
 
+/// The types of the variables in scope, by variable id
+#[derive(Default)]
+struct TypeEnv {
+    var_types: HashMap<usize, Type>,
+}
+
 trait Typecheck {
-    fn typecheck(&mut self) -> Result<Type, Error>;
+    fn typecheck(&mut self, env: &mut TypeEnv) -> Result<Type, Error>;
 }
 
 impl Typecheck for Expr {
-    fn typecheck(&mut self) -> Result<Type, Error> {
+    fn typecheck(&mut self, env: &mut TypeEnv) -> Result<Type, Error> {
         match self {
-            Expr::Builtin(b, _pos) =>
-                b.typecheck(),
+            Expr::Builtin(b, pos) =>
+                b.signature(&mut CallSite::new(&mut [], &[], *pos, env)),
+
+            Expr::Literal(lit, _pos) =>
+                Ok(lit.typ()),
 
             Expr::UnresolvedIdentifier(identifier) =>
                 // We should not reach here with some identifiers still being unresolved
@@ -73,200 +163,1107 @@ impl Typecheck for Expr {
                 panic!("Unexpected unresolved identifier during typechecking: {:?}", identifier.name),
 
             Expr::FunCall(fcall) =>
-                fcall.typecheck(),
+                fcall.typecheck(env),
+
+            Expr::Lambda(lambda) =>
+                typecheck_lambda(lambda, &[], env),
+
+            Expr::Let(let_expr) =>
+                typecheck_let(let_expr, &[], env),
 
-            Expr::ReadVar(_stream_var) => todo!(),
+            Expr::Var(_idn, var) =>
+                // Name resolution and scoping guarantee that the binding was typechecked first
+                Ok(env.var_types[&var.id].clone()),
+
+            Expr::ReadVar(..) =>
+                // Stream variables are only introduced at runtime
+                unreachable!("Unexpected stream variable during typechecking"),
         }
     }
 }
 
 impl Typecheck for FunCall {
-    fn typecheck(&mut self) -> Result<Type, Error> {
-        let fn_type =
-            match self.function.as_mut() {
-                // TODO we would need to introduce full-fledged type equations here
-                Expr::Builtin(Builtin::Filter, _pos) =>
-                    typecheck_filter(&mut self.arguments)?,
-                Expr::Builtin(Builtin::Map, _pos) =>
-                    typecheck_map(&mut self.arguments)?,
-                _ => self.function.typecheck()?,
+    fn typecheck(&mut self, env: &mut TypeEnv) -> Result<Type, Error> {
+        typecheck_call(self, &[], env)
+    }
+}
+
+/// Type of a function expression, knowing that it will be applied to
+/// (at least) arguments of the given types.
+/// This is what allows passing polymorphic builtins as arguments, e.g. `map len ...`
+fn typecheck_applied(function: &mut Expr, arg_types: &[Type], env: &mut TypeEnv) -> Result<Type, Error> {
+    match function {
+        Expr::Builtin(b, pos) =>
+            b.signature(&mut CallSite::new(&mut [], arg_types, *pos, env)),
+        Expr::FunCall(fcall) =>
+            typecheck_call(fcall, arg_types, env),
+        Expr::Lambda(lambda) =>
+            typecheck_lambda(lambda, arg_types, env),
+        Expr::Let(let_expr) =>
+            typecheck_let(let_expr, arg_types, env),
+        _ =>
+            function.typecheck(env),
+    }
+}
+
+/// Lambda parameters get their type from their annotation, or else from the
+/// type of the arguments the lambda will be applied to
+fn typecheck_lambda(lambda: &mut Lambda, arg_types: &[Type], env: &mut TypeEnv) -> Result<Type, Error> {
+    let mut parameters = Vec::with_capacity(lambda.params.len());
+    for (idx, param) in lambda.params.iter().enumerate() {
+        let param_type =
+            match (&param.annotation, arg_types.get(idx)) {
+                (Some(annotation), _) => annotation.typ.clone(),
+                (None, Some(arg_type)) => arg_type.clone(),
+                (None, None) => return Err(Error::CannotInferType(param.name.clone())),
             };
 
-        match fn_type {
-            Type::Function { parameters, return_type } => {
-                let n_args = self.arguments.len();
-                let n_params = parameters.len();
-
-                // Start by checking the number of arguments provided to the function
-                assert_ne!(n_params, 0);
-                if n_args < n_params {
-                    return Err(Error::NotEnoughArguments {
-                        expected: n_params,
-                        found:    n_args,
-                        err_pos:  self.arguments.last().unwrap().position().right_after()
-                    });
-                }
-                else if n_args > n_params {
-                    return Err(Error::TooManyArguments {
-                        expected: n_params,
-                        found:    n_args,
-                        err_pos:  self.arguments[n_params].position()
-                    });
+        // Parameters are bound to runtime values, which can't be functions or streams
+        if matches!(param_type, Type::Function { .. } | Type::Stream(_)) {
+            let err_pos = param.annotation.as_ref().map(|a| a.position).unwrap_or(param.name.position);
+            return Err(Error::InvalidParamType { found: param_type.to_string(), err_pos });
+        }
+
+        env.var_types.insert(param.var.id, param_type.clone());
+        parameters.push(param_type);
+    }
+
+    // The body itself can be a function if more arguments are provided
+    let body_arg_types = arg_types.get(parameters.len()..).unwrap_or_default();
+    let return_type = typecheck_applied(&mut lambda.body, body_arg_types, env)?;
+
+    Ok(Type::function(parameters, return_type))
+}
+
+fn typecheck_let(let_expr: &mut Let, arg_types: &[Type], env: &mut TypeEnv) -> Result<Type, Error> {
+    let binding = &let_expr.binding;
+
+    // An annotated function type tells us the type of the arguments
+    let value_type =
+        match binding.annotation.as_ref().map(|a| &a.typ) {
+            Some(Type::Function { parameters, .. }) =>
+                typecheck_applied(&mut let_expr.value, parameters, env)?,
+            _ =>
+                let_expr.value.typecheck(env)?,
+        };
+
+    let var_type = check_annotation(binding, value_type, let_expr.value.position())?;
+
+    let_expr.inline =
+        match var_type {
+            Type::Function { .. } => Some(Inlining::Function),
+            Type::Stream(_) => Some(Inlining::Stream),
+            _ => None,
+        };
+
+    env.var_types.insert(binding.var.id, var_type);
+    typecheck_applied(&mut let_expr.body, arg_types, env)
+}
+
+/// The type of a bound variable, given the type of the value it is bound to
+fn check_annotation(binding: &Binding, value_type: Type, value_pos: ParsePos) -> Result<Type, Error> {
+    match &binding.annotation {
+        Some(annotation) if annotation.typ.accepts(&value_type) =>
+            Ok(annotation.typ.clone()),
+        Some(annotation) => {
+            let mismatch = Error::TypeMismatch {
+                expected: annotation.typ.to_string(),
+                found:    value_type.to_string(),
+                err_pos:  value_pos
+            };
+            Err(mismatch.with_label(annotation.position, "expected because of this annotation".into()))
+        }
+        None =>
+            Ok(value_type),
+    }
+}
+
+/// Type checks a function call, possibly followed by `pending` arguments that are only
+/// known by their type.
+/// When not all the parameters are provided, this is a partial application
+/// and the result is a function of the remaining parameters.
+fn typecheck_call(fcall: &mut FunCall, pending: &[Type], env: &mut TypeEnv) -> Result<Type, Error> {
+    let call_pos = fcall.function.position();
+    let mut site = CallSite::new(&mut fcall.arguments, pending, call_pos, env);
+
+    let fn_type =
+        match fcall.function.as_mut() {
+            Expr::Builtin(b, _pos) => b.signature(&mut site)?,
+            lambda@(Expr::Lambda(_) | Expr::Let(_)) => {
+                // Lambda parameters get their type from the arguments
+                let mut arg_types = Vec::with_capacity(site.arguments.len() + pending.len());
+                for idx in 0..site.arguments.len() {
+                    arg_types.push(site.arg_type(idx)?.unwrap());
                 }
+                arg_types.extend_from_slice(pending);
+                typecheck_applied(lambda, &arg_types, site.env)?
+            }
+            other => other.typecheck(site.env)?,
+        };
 
-                // Check the types of the arguments
-                for (param_type, arg)
-                in parameters.into_iter()
-                    .zip(self.arguments.iter_mut())
-                {
-                    let arg_type = arg.typecheck()?;
-                    if arg_type != param_type {
-                        return Err(Error::WrongArgType {
-                            expected: param_type.to_string(),
-                            found:    arg_type.to_string(),
-                            err_pos:  arg.position()
-                        });
+    match fn_type {
+        Type::Function { mut parameters, return_type } => {
+            let n_args = site.arguments.len();
+            let n_params = parameters.len();
+
+            // Start by checking the number of arguments provided to the function
+            assert_ne!(n_params, 0);
+            if n_args > n_params {
+                return Err(Error::TooManyArguments {
+                    expected: n_params,
+                    found:    n_args,
+                    err_pos:  site.arguments[n_params].position()
+                });
+            }
+
+            // Check the types of the arguments
+            for (idx, param_type) in parameters.iter().enumerate().take(n_args) {
+                let arg_type =
+                    match param_type {
+                        Type::Function { parameters: fn_params, .. } =>
+                            site.applied_type(idx, fn_params)?,
+                        _ =>
+                            site.arg_type(idx)?,
+                    }
+                    .unwrap();
+
+                if !param_type.accepts(&arg_type) {
+                    let wrong_type = site.wrong_type(idx, param_type.to_string(), &arg_type);
+                    if let Type::Function { .. } = param_type {
+                        // The expected function type is usually determined by the streams
+                        return Err(site.label_streams(wrong_type));
                     }
+                    return Err(wrong_type);
                 }
+            }
 
-                // Typecheck suceeded
+            // The pending arguments are only known by their type, but they still need to match
+            let remaining = parameters.split_off(n_args);
+            for (idx, (param_type, arg_type)) in remaining.iter().zip(pending).enumerate() {
+                if !param_type.accepts(arg_type) {
+                    return Err(site.wrong_type(n_args + idx, param_type.to_string(), arg_type));
+                }
+            }
+
+            // Typecheck suceeded
+            if remaining.is_empty() {
                 Ok(*return_type)
             }
-            _ => Err(Error::NotAFunction(self.function.position())),
+            else {
+                Ok(Type::Function { parameters: remaining, return_type })
+            }
+        }
+        _ => Err(Error::NotAFunction(call_pos)),
+    }
+}
+
+/* CallSite */
+
+/// The arguments of a function call, as seen while typechecking the function.
+/// The first arguments are expressions, the last (pending) ones are only known by their type.
+/// Argument types are computed at most once.
+struct CallSite<'a> {
+    arguments: &'a mut [Expr],
+    pending:   &'a [Type],
+    arg_types: Vec<Option<Type>>,
+    call_pos:  ParsePos,
+    env:       &'a mut TypeEnv,
+}
+
+impl<'a> CallSite<'a> {
+    fn new(arguments: &'a mut [Expr], pending: &'a [Type], call_pos: ParsePos, env: &'a mut TypeEnv) -> Self {
+        let arg_types = vec![None; arguments.len()];
+        CallSite { arguments, pending, arg_types, call_pos, env }
+    }
+
+    /// Type of the argument at the given index, if it is provided
+    fn arg_type(&mut self, idx: usize) -> Result<Option<Type>, Error> {
+        self.applied_type(idx, &[])
+    }
+
+    /// Type of the (function) argument at the given index, if it is provided,
+    /// knowing that it will be applied to arguments of the given types
+    fn applied_type(&mut self, idx: usize, param_types: &[Type]) -> Result<Option<Type>, Error> {
+        if idx < self.arguments.len() {
+            if self.arg_types[idx].is_none() {
+                let arg_type = typecheck_applied(&mut self.arguments[idx], param_types, self.env)?;
+                self.arg_types[idx] = Some(arg_type);
+            }
+            Ok(self.arg_types[idx].clone())
+        }
+        else {
+            Ok(self.pending.get(idx - self.arguments.len()).cloned())
+        }
+    }
+
+    /// Type of the argument at the given index, which is required to determine
+    /// the signature of a function of `n_params` parameters
+    fn require(&mut self, idx: usize, n_params: usize) -> Result<Type, Error> {
+        match self.arg_type(idx)? {
+            Some(arg_type) => Ok(arg_type),
+            None => Err(self.missing(n_params)),
+        }
+    }
+
+    fn position(&self, idx: usize) -> ParsePos {
+        match self.arguments.get(idx) {
+            Some(arg) => arg.position(),
+            // Pending arguments don't have a position: blame the function
+            None => self.call_pos,
+        }
+    }
+
+    fn missing(&self, n_params: usize) -> Error {
+        let last_pos =
+            match self.arguments.last() {
+                Some(arg) => arg.position(),
+                None => self.call_pos,
+            };
+
+        Error::NotEnoughArguments {
+            expected: n_params,
+            found:    self.arguments.len(),
+            err_pos:  last_pos.right_after()
         }
     }
+
+    fn wrong_type(&self, idx: usize, expected: String, found: &Type) -> Error {
+        Error::WrongArgType {
+            expected,
+            found:   found.to_string(),
+            err_pos: self.position(idx)
+        }
+    }
+
+    /// Adds the item type of the stream arguments (that were typechecked) to an error
+    fn label_streams(&self, error: Error) -> Error {
+        self.arg_types
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, arg_type)| Some((idx, arg_type.as_ref()?.stream_item()?)))
+            .fold(error, |error, (idx, item_type)|
+                error.with_label(self.position(idx), format!("this stream has item type {}", item_type)))
+    }
 }
 
-impl Typecheck for Builtin {
-    fn typecheck(&mut self) -> Result<Type, Error> {
+/* Builtin signatures */
+
+impl Builtin {
+    /// The type of this builtin at the given call site.
+    /// Polymorphic builtins deduce their actual type from the arguments.
+    fn signature(&mut self, site: &mut CallSite) -> Result<Type, Error> {
         match self {
             Builtin::Stdin =>
                 Ok(Type::stream(Type::String)),
+            Builtin::File =>
+                Ok(Type::function(vec![Type::String], Type::stream(Type::String))),
+            Builtin::Concat | Builtin::Interleave =>
+                typecheck_concat(site),
+            Builtin::Zip =>
+                typecheck_zip(site),
             Builtin::RegexMatch(..) =>
                 Ok(Type::function(vec![Type::String], Type::Bool)),
             Builtin::RegexSubst(..) =>
                 Ok(Type::function(vec![Type::String], Type::String)),
+            Builtin::RegexCapture(..) =>
+                Ok(Type::function(vec![Type::String], Type::maybe(Type::String))),
             Builtin::ToNumber =>
-                // TODO we should be able to support number to number as well
-                Ok(Type::function(vec![Type::String], Type::Number)),
-
+                typecheck_conversion(site, Type::Number),
+            Builtin::TryToNumber =>
+                typecheck_conversion(site, Type::maybe(Type::Number)),
+            Builtin::ToInt =>
+                typecheck_conversion(site, Type::Int),
+            Builtin::TryToInt =>
+                typecheck_conversion(site, Type::maybe(Type::Int)),
+            Builtin::ToFloat =>
+                typecheck_conversion(site, Type::Float),
+            Builtin::TryToFloat =>
+                typecheck_conversion(site, Type::maybe(Type::Float)),
+            Builtin::Arith(_) =>
+                typecheck_arith(site),
+            Builtin::Lineno =>
+                Ok(Type::Int),
 
-            Builtin::Filter | Builtin::Map =>
-                todo!("full type equations"),
+            // TODO we would need to introduce full-fledged type equations here
+            Builtin::Filter | Builtin::TailAfter | Builtin::TakeWhile | Builtin::DropWhile =>
+                typecheck_filter(site),
+            Builtin::Between(_) =>
+                typecheck_between(site),
+            Builtin::Context =>
+                typecheck_context(site),
+            Builtin::Map =>
+                typecheck_map(site),
+            Builtin::Flatten =>
+                typecheck_flatten(site),
+            Builtin::FlatMap { stream } =>
+                typecheck_flat_map(site, stream),
+            Builtin::SplitLines =>
+                typecheck_split_lines(site),
+            Builtin::Split =>
+                typecheck_split(site),
+            Builtin::Words =>
+                Ok(Type::function(vec![Type::String], Type::list(Type::String))),
+            Builtin::At =>
+                typecheck_at(site),
+            Builtin::TryAt =>
+                typecheck_try_at(site),
+            Builtin::Len =>
+                typecheck_len(site),
+            Builtin::Join =>
+                typecheck_join(site),
+            Builtin::HashJoin(mode) =>
+                typecheck_hash_join(site, *mode),
+            Builtin::FilterSome =>
+                typecheck_filter_some(site),
+            Builtin::Take | Builtin::Drop | Builtin::Tail | Builtin::Sample | Builtin::Every =>
+                typecheck_count_slice(site),
+            Builtin::Bernoulli =>
+                typecheck_bernoulli(site),
+            Builtin::Lines =>
+                typecheck_lines(site),
+            Builtin::Enumerate =>
+                typecheck_enumerate(site),
+            Builtin::Uniq | Builtin::Distinct | Builtin::Sort | Builtin::ReverseSort =>
+                typecheck_same_stream(site),
+            Builtin::UniqCount =>
+                typecheck_uniq_count(site),
+            Builtin::DistinctBy | Builtin::SortBy | Builtin::ReverseSortBy =>
+                typecheck_keyed_same_stream(site),
+            Builtin::Default =>
+                typecheck_default(site),
+            Builtin::IsSome =>
+                typecheck_is_some(site),
+            Builtin::Window | Builtin::Chunks =>
+                typecheck_window(site),
+            Builtin::MovingAvg =>
+                typecheck_moving_avg(site),
+            Builtin::Histogram =>
+                typecheck_histogram(site, false),
+            Builtin::LogHistogram =>
+                typecheck_histogram(site, true),
+            Builtin::Bars =>
+                typecheck_bars(site),
+            Builtin::Quantiles =>
+                typecheck_percentile(site, true),
+            Builtin::GroupBy(_) =>
+                typecheck_group_by(site),
+            Builtin::BucketByTime | Builtin::BucketFill =>
+                typecheck_bucket_by_time(site),
+            Builtin::Top =>
+                typecheck_top(site),
+            Builtin::Frequencies =>
+                typecheck_uniq_count(site),
+            Builtin::Frequent =>
+                typecheck_frequent(site),
+            Builtin::First =>
+                typecheck_tuple_elem(site, 0),
+            Builtin::Second =>
+                typecheck_tuple_elem(site, 1),
+            Builtin::Reduce(op) =>
+                typecheck_reduce(site, op),
+            Builtin::Fold =>
+                typecheck_fold(site, false),
+            Builtin::Scan =>
+                typecheck_fold(site, true),
+            Builtin::Percentile =>
+                typecheck_percentile(site, false),
+            Builtin::List =>
+                typecheck_list(site),
         }
     }
 }
 
-fn mut_pair<T>(data: &mut [T]) -> Option<(&mut T, &mut T)> {
-    if data.len() != 2 {
-        return None;
+impl Literal {
+    fn typ(&self) -> Type {
+        match self {
+            Literal::String(_) => Type::String,
+            Literal::Int(_) => Type::Int,
+            Literal::Float(_) => Type::Float,
+        }
     }
+}
 
-    let mut iter = data.into_iter();
-    let first = iter.next().unwrap();
-    let second = iter.next().unwrap();
-    Some((first, second))
+/// Item type of the stream argument at the given index
+fn stream_arg_item(site: &mut CallSite, idx: usize, n_params: usize) -> Result<Type, Error> {
+    match site.require(idx, n_params)? {
+        Type::Stream(item_type) => Ok(*item_type),
+        other => Err(site.wrong_type(idx, "any stream type".into(), &other)),
+    }
 }
 
-fn typecheck_filter(arguments: &mut [Expr]) -> Result<Type, Error> {
-    let (filter_fn, data_source) =
-        match mut_pair(arguments) {
-            Some(pair) => pair,
-            None => {
-                // Ugly hack: return a pointless function of 2 arguments that
-                // will fail in the calling FunCall typechecking
-                return Ok(Type::function(vec![Type::Bool, Type::Bool], Type::Bool));
-            }
-        };
+/// Item type of a stream operator that applies a function to every item of its data source.
+/// This is given by the data source, or by the function itself in the case of a partial application.
+fn applied_item_type(site: &mut CallSite, fn_idx: usize, source_idx: usize, n_params: usize) -> Result<Type, Error> {
+    if site.arg_type(source_idx)?.is_some() {
+        return stream_arg_item(site, source_idx, n_params);
+    }
 
-    let source_type = data_source.typecheck()?;
-    let source_items: &Type =
-        match &source_type {
-            Type::Stream(item_type) => item_type,
-            _ => return Err(Error::WrongArgType {
-                expected: "any stream type".into(),
-                found:    source_type.to_string(),
-                err_pos:  data_source.position()
-            }),
-        };
+    match site.require(fn_idx, n_params)? {
+        Type::Function { mut parameters, .. } if parameters.len() == 1 =>
+            Ok(parameters.pop().unwrap()),
+        _ =>
+            Err(site.missing(n_params)),
+    }
+}
+
+fn typecheck_filter(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = applied_item_type(site, 0, 1, 2)?;
 
     // The filter function must go from the data source's item type to boolean
-    let fn_type = filter_fn.typecheck()?;
     let expected_fn_type = Type::function(vec![source_items.clone()], Type::Bool);
-    if fn_type != expected_fn_type {
-        return Err(Error::WrongArgType {
-            expected: expected_fn_type.to_string(),
-            found:    fn_type.to_string(),
-            err_pos:  filter_fn.position()
-        });
-    }
+    let source_type = Type::stream(source_items);
 
     let return_type = Type::function(vec![expected_fn_type, source_type.clone()], source_type);
     Ok(return_type)
 }
 
-fn typecheck_map(arguments: &mut [Expr]) -> Result<Type, Error> {
-    let (map_fn, data_source) =
-        match mut_pair(arguments) {
-            Some(pair) => pair,
-            None => {
-                // Ugly hack: return a pointless function of 2 arguments that
-                // will fail in the calling FunCall typechecking
-                return Ok(Type::function(vec![Type::Bool, Type::Bool], Type::Bool));
-            }
-        };
+fn typecheck_between(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = applied_item_type(site, 0, 2, 3)?;
 
-    let source_type = data_source.typecheck()?;
-    let source_items: &Type =
-        match &source_type {
-            Type::Stream(item_type) => item_type,
-            _ => return Err(Error::WrongArgType {
-                expected: "any stream type".into(),
-                found:    source_type.to_string(),
-                err_pos:  data_source.position()
-            }),
-        };
+    // Both the start and end predicates are applied to the items
+    let predicate_type = Type::function(vec![source_items.clone()], Type::Bool);
+    let source_type = Type::stream(source_items);
+
+    Ok(Type::function(vec![predicate_type.clone(), predicate_type, source_type.clone()], source_type))
+}
+
+fn typecheck_context(site: &mut CallSite) -> Result<Type, Error> {
+    // Like grep, groups of lines are separated by "--" lines
+    let lines = Type::stream(Type::String);
+    if let Some(source_type) = site.arg_type(3)? {
+        if source_type != lines {
+            return Err(site.wrong_type(3, lines.to_string(), &source_type));
+        }
+    }
+
+    let predicate_type = Type::function(vec![Type::String], Type::Bool);
+    Ok(Type::function(vec![Type::Int, Type::Int, predicate_type, lines.clone()], lines))
+}
 
-    // The mapping function must take the data source's item type as argument
-    let fn_type = map_fn.typecheck()?;
-    // TODO should we remember this mapped-to type in the Map node?
-    let mapped_to =
+fn typecheck_map(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = applied_item_type(site, 0, 1, 2)?;
+    let (fn_type, mapped_to) = item_fn_type(site, 0, &source_items)?;
+
+    let return_type = Type::function(vec![fn_type, Type::stream(source_items)], Type::stream(mapped_to));
+    Ok(return_type)
+}
+
+/// The type of a function argument that is applied to the items of a stream,
+/// along with its return type
+fn item_fn_type(site: &mut CallSite, fn_idx: usize, source_items: &Type) -> Result<(Type, Type), Error> {
+    // The function must take the data source's item type as argument
+    let fn_type = site.applied_type(fn_idx, std::slice::from_ref(source_items))?.unwrap();
+    let return_type =
         match &fn_type {
             Type::Function { parameters, return_type } => {
                 if parameters.len() == 1 {
                     let single_param = parameters.first().unwrap();
-                    if single_param == source_items {
+                    if single_param.accepts(source_items) {
                         // Typecheck ok
                         // Give back the function's return type so we can build the final type out of it
-                        return_type.clone()
+                        return_type.as_ref().clone()
                     }
                     else {
-                        return Err(Error::WrongArgType {
-                            expected: format!("fn ({}) -> anything", source_items.to_string()),
-                            found:    single_param.to_string(),
-                            err_pos:  map_fn.position()
-                        });
+                        let wrong_type = site.wrong_type(
+                            fn_idx,
+                            format!("fn ({}) -> anything", source_items),
+                            &fn_type);
+                        return Err(site.label_streams(wrong_type));
                     }
                 }
                 else {
-                    return Err(Error::WrongArgType {
-                        expected: "a function of a single argument".into(),
-                        found:    fn_type.to_string(),
-                        err_pos:  map_fn.position()
-                    });
+                    return Err(site.wrong_type(fn_idx, "a function of a single argument".into(), &fn_type));
                 }
             }
             _ => {
-                return Err(Error::WrongArgType {
-                    expected: "any function type".into(),
-                    found:    fn_type.to_string(),
-                    err_pos:  map_fn.position()
-                });
+                return Err(site.wrong_type(fn_idx, "any function type".into(), &fn_type));
             }
         };
 
-    let return_type = Type::function(vec![fn_type, source_type], Type::Stream(mapped_to));
-    Ok(return_type)
+    Ok((fn_type, return_type))
+}
+
+fn typecheck_flatten(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = stream_arg_item(site, 0, 1)?;
+
+    match source_items {
+        Type::List(elem_type) =>
+            Ok(Type::function(vec![Type::stream(Type::List(elem_type.clone()))], Type::Stream(elem_type))),
+        other =>
+            Err(site.wrong_type(0, "a stream of lists".into(), &Type::stream(other))),
+    }
+}
+
+fn typecheck_flat_map(site: &mut CallSite, stream: &mut bool) -> Result<Type, Error> {
+    let source_items = applied_item_type(site, 0, 1, 2)?;
+    let (fn_type, mapped_to) = item_fn_type(site, 0, &source_items)?;
+
+    // A stream is started over for each item, e.g. to read the files with the given names
+    *stream = matches!(mapped_to, Type::Stream(_));
+    match mapped_to {
+        Type::List(elem_type) | Type::Stream(elem_type) =>
+            Ok(Type::function(vec![fn_type, Type::stream(source_items)], Type::Stream(elem_type))),
+        _ => {
+            let wrong_type = site.wrong_type(0, format!("fn ({}) -> list or stream of anything", source_items), &fn_type);
+            Err(site.label_streams(wrong_type))
+        }
+    }
+}
+
+fn typecheck_split_lines(site: &mut CallSite) -> Result<Type, Error> {
+    // Same separators as split
+    let separator_type =
+        match typecheck_split(site)? {
+            Type::Function { mut parameters, .. } => parameters.swap_remove(0),
+            _ => unreachable!(),
+        };
+
+    let lines = Type::stream(Type::String);
+    Ok(Type::function(vec![separator_type, lines.clone()], lines))
+}
+
+fn typecheck_split(site: &mut CallSite) -> Result<Type, Error> {
+    // The separator is either a string or a regex literal
+    let separator_type =
+        match site.arguments.first() {
+            Some(Expr::Builtin(Builtin::RegexMatch(_), _pos)) =>
+                Type::function(vec![Type::String], Type::Bool),
+            _ =>
+                Type::String,
+        };
+
+    Ok(Type::function(vec![separator_type, Type::String], Type::list(Type::String)))
+}
+
+fn typecheck_at(site: &mut CallSite) -> Result<Type, Error> {
+    match site.require(1, 2)? {
+        Type::List(elem_type) =>
+            Ok(Type::function(vec![Type::Int, Type::List(elem_type.clone())], *elem_type)),
+        other =>
+            Err(site.wrong_type(1, "any list type".into(), &other)),
+    }
+}
+
+fn typecheck_try_at(site: &mut CallSite) -> Result<Type, Error> {
+    match typecheck_at(site)? {
+        Type::Function { parameters, return_type } =>
+            Ok(Type::function(parameters, Type::Maybe(return_type))),
+        _ => unreachable!(),
+    }
+}
+
+fn typecheck_len(site: &mut CallSite) -> Result<Type, Error> {
+    match site.require(0, 1)? {
+        sized@(Type::List(_) | Type::String) =>
+            Ok(Type::function(vec![sized], Type::Int)),
+        other =>
+            Err(site.wrong_type(0, "a list or a string".into(), &other)),
+    }
+}
+
+fn typecheck_join(site: &mut CallSite) -> Result<Type, Error> {
+    // The elements are formatted the same way as top-level values
+    match site.require(1, 2)? {
+        Type::List(elem_type) if elem_type.is_base() =>
+            Ok(Type::function(vec![Type::String, Type::List(elem_type)], Type::String)),
+        other =>
+            Err(site.wrong_type(1, "a list of a base type".into(), &other)),
+    }
+}
+
+fn typecheck_hash_join(site: &mut CallSite, mode: JoinMode) -> Result<Type, Error> {
+    let left_items = applied_item_type(site, 0, 2, 4)?;
+    let right_items = applied_item_type(site, 1, 3, 4)?;
+    let (left_key_fn, left_key) = item_fn_type(site, 0, &left_items)?;
+    let (right_key_fn, right_key) = item_fn_type(site, 1, &right_items)?;
+
+    // Keys are compared as runtime values, so an int never matches a float
+    if left_key != right_key {
+        let wrong_type = site.wrong_type(1, format!("fn ({}) -> {}", right_items, left_key), &right_key_fn);
+        return Err(wrong_type.with_label(site.position(0), format!("this key has type {}", left_key)));
+    }
+
+    let output_items =
+        match mode {
+            JoinMode::Inner => Type::Tuple(vec![left_items.clone(), right_items.clone()]),
+            JoinMode::Left => Type::Tuple(vec![left_items.clone(), Type::maybe(right_items.clone())]),
+            JoinMode::Anti => left_items.clone(),
+        };
+
+    let parameters = vec![left_key_fn, right_key_fn, Type::stream(left_items), Type::stream(right_items)];
+    Ok(Type::function(parameters, Type::stream(output_items)))
+}
+
+fn typecheck_conversion(site: &mut CallSite, return_type: Type) -> Result<Type, Error> {
+    // Conversions either parse strings or convert between numeric types
+    let arg_type = site.require(0, 1)?;
+    if arg_type == Type::String || arg_type.is_numeric() {
+        Ok(Type::function(vec![arg_type], return_type))
+    }
+    else {
+        Err(site.wrong_type(0, "a string or any numeric type".into(), &arg_type))
+    }
+}
+
+fn typecheck_arith(site: &mut CallSite) -> Result<Type, Error> {
+    let mut operand_types = Vec::with_capacity(2);
+    for idx in 0..2 {
+        let operand_type = site.require(idx, 2)?;
+        if !operand_type.is_numeric() {
+            return Err(site.wrong_type(idx, "any numeric type".into(), &operand_type));
+        }
+        operand_types.push(operand_type);
+    }
+
+    let result_type = Type::promote(&operand_types[0], &operand_types[1]);
+    Ok(Type::function(operand_types, result_type))
+}
+
+fn typecheck_filter_some(site: &mut CallSite) -> Result<Type, Error> {
+    match stream_arg_item(site, 0, 1)? {
+        Type::Maybe(inner_type) =>
+            Ok(Type::function(vec![Type::stream(Type::Maybe(inner_type.clone()))], Type::Stream(inner_type))),
+        other =>
+            Err(site.wrong_type(0, "a stream of optional values".into(), &Type::stream(other))),
+    }
+}
+
+fn typecheck_count_slice(site: &mut CallSite) -> Result<Type, Error> {
+    // Only keeps or removes items, so the item type is preserved
+    let source_type = Type::stream(stream_arg_item(site, 1, 2)?);
+    Ok(Type::function(vec![Type::Int, source_type.clone()], source_type))
+}
+
+fn typecheck_bernoulli(site: &mut CallSite) -> Result<Type, Error> {
+    let probability_type = site.require(0, 2)?;
+    if !probability_type.is_numeric() {
+        return Err(site.wrong_type(0, "any numeric type".into(), &probability_type));
+    }
+
+    let source_type = Type::stream(stream_arg_item(site, 1, 2)?);
+    Ok(Type::function(vec![probability_type, source_type.clone()], source_type))
+}
+
+fn typecheck_lines(site: &mut CallSite) -> Result<Type, Error> {
+    let source_type = Type::stream(stream_arg_item(site, 2, 3)?);
+    Ok(Type::function(vec![Type::Int, Type::Int, source_type.clone()], source_type))
+}
+
+fn typecheck_enumerate(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = stream_arg_item(site, 0, 1)?;
+    let numbered = Type::Tuple(vec![Type::Int, source_items.clone()]);
+    Ok(Type::function(vec![Type::stream(source_items)], Type::stream(numbered)))
+}
+
+fn typecheck_same_stream(site: &mut CallSite) -> Result<Type, Error> {
+    let source_type = Type::stream(stream_arg_item(site, 0, 1)?);
+    Ok(Type::function(vec![source_type.clone()], source_type))
+}
+
+fn typecheck_uniq_count(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = stream_arg_item(site, 0, 1)?;
+    let counted = Type::Tuple(vec![Type::Int, source_items.clone()]);
+    Ok(Type::function(vec![Type::stream(source_items)], Type::stream(counted)))
+}
+
+fn typecheck_keyed_same_stream(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = applied_item_type(site, 0, 1, 2)?;
+    let (key_fn_type, _key_type) = item_fn_type(site, 0, &source_items)?;
+    let source_type = Type::stream(source_items);
+    Ok(Type::function(vec![key_fn_type, source_type.clone()], source_type))
+}
+
+/// Histograms give (count, lower bound, upper bound) tuples, like uniq_c puts the count first
+fn typecheck_histogram(site: &mut CallSite, log: bool) -> Result<Type, Error> {
+    // Either a bucket width (or log base), or the boundaries between buckets
+    let layout_type = site.require(0, 2)?;
+    let layout_ok =
+        match &layout_type {
+            Type::List(bound_type) if !log => bound_type.is_numeric(),
+            other => other.is_numeric(),
+        };
+    if !layout_ok {
+        let expected = if log { "any numeric type" } else { "a number or a list of numbers" };
+        return Err(site.wrong_type(0, expected.into(), &layout_type));
+    }
+
+    let source_type = site.require(1, 2)?;
+    match source_type.stream_item() {
+        Some(item_type) if item_type.is_numeric() => {
+            let buckets = Type::stream(Type::Tuple(vec![Type::Int, Type::Float, Type::Float]));
+            Ok(Type::function(vec![layout_type, source_type], buckets))
+        }
+        _ =>
+            Err(site.wrong_type(1, "a stream of any numeric type".into(), &source_type)),
+    }
+}
+
+/// Appends a bar to tuples that start with a count
+fn typecheck_bars(site: &mut CallSite) -> Result<Type, Error> {
+    let source_type = site.require(0, 1)?;
+    match source_type.stream_item() {
+        Some(Type::Tuple(elems)) if elems[0] == Type::Int => {
+            let mut with_bar = elems.clone();
+            with_bar.push(Type::String);
+            Ok(Type::function(vec![source_type], Type::stream(Type::Tuple(with_bar))))
+        }
+        _ =>
+            Err(site.wrong_type(0, "a stream of tuples starting with an int count".into(), &source_type)),
+    }
+}
+
+fn typecheck_concat(site: &mut CallSite) -> Result<Type, Error> {
+    let first_items = stream_arg_item(site, 0, 2)?;
+
+    // Both streams must have the same item type (or a number type that accepts both)
+    let items =
+        match site.arg_type(1)? {
+            None => first_items,
+            Some(Type::Stream(second_items)) if first_items.accepts(&second_items) => first_items,
+            Some(Type::Stream(second_items)) if second_items.accepts(&first_items) => *second_items,
+            Some(other) => {
+                let expected = Type::stream(first_items).to_string();
+                return Err(site.label_streams(site.wrong_type(1, expected, &other)));
+            }
+        };
+
+    let source_type = Type::stream(items);
+    Ok(Type::function(vec![source_type.clone(), source_type.clone()], source_type))
+}
+
+fn typecheck_zip(site: &mut CallSite) -> Result<Type, Error> {
+    let left_items = stream_arg_item(site, 0, 2)?;
+    let right_items = stream_arg_item(site, 1, 2)?;
+
+    let pairs = Type::stream(Type::Tuple(vec![left_items.clone(), right_items.clone()]));
+    Ok(Type::function(vec![Type::stream(left_items), Type::stream(right_items)], pairs))
+}
+
+fn typecheck_window(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = stream_arg_item(site, 1, 2)?;
+    let windows = Type::stream(Type::list(source_items.clone()));
+    Ok(Type::function(vec![Type::Int, Type::stream(source_items)], windows))
+}
+
+fn typecheck_moving_avg(site: &mut CallSite) -> Result<Type, Error> {
+    let source_type = site.require(1, 2)?;
+    match source_type.stream_item() {
+        Some(item_type) if item_type.is_numeric() =>
+            Ok(Type::function(vec![Type::Int, source_type], Type::stream(Type::Float))),
+        _ =>
+            Err(site.wrong_type(1, "a stream of any numeric type".into(), &source_type)),
+    }
+}
+
+fn typecheck_reduce(site: &mut CallSite, op: &mut ReduceOp) -> Result<Type, Error> {
+    let source_items = stream_arg_item(site, 0, 1)?;
+
+    let return_type =
+        match op {
+            ReduceOp::Count =>
+                Type::Int,
+            ReduceOp::Min | ReduceOp::Max =>
+                Type::maybe(source_items.clone()),
+            ReduceOp::Sum { .. } | ReduceOp::Avg if !source_items.is_numeric() =>
+                return Err(site.wrong_type(0, "a stream of any numeric type".into(), &Type::stream(source_items))),
+            ReduceOp::Sum { float } => {
+                // So that the sum of no floats is a float too
+                *float = source_items == Type::Float;
+                source_items.clone()
+            }
+            ReduceOp::Avg =>
+                Type::maybe(Type::Float),
+        };
+
+    Ok(Type::function(vec![Type::stream(source_items)], return_type))
+}
+
+fn typecheck_group_by(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = applied_item_type(site, 0, 2, 3)?;
+    let (key_fn_type, key_type) = item_fn_type(site, 0, &source_items)?;
+
+    // The aggregation is applied to the stream of the items of each group
+    let group_type = Type::stream(source_items);
+    let (agg_type, value_type) = aggregation_type(site, 1, 3, &group_type)?;
+
+    let output_type = Type::stream(Type::Tuple(vec![key_type, value_type]));
+    Ok(Type::function(vec![key_fn_type, agg_type, group_type], output_type))
+}
+
+/// Like group_by, with the groups given by the time bucket of the items
+fn typecheck_bucket_by_time(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = applied_item_type(site, 1, 3, 4)?;
+    let (ts_fn_type, ts_type) = item_fn_type(site, 1, &source_items)?;
+    if ts_type != Type::String && !ts_type.is_numeric() {
+        let expected = format!("fn ({}) -> string or any numeric type", source_items);
+        return Err(site.wrong_type(1, expected, &ts_fn_type));
+    }
+
+    let group_type = Type::stream(source_items);
+    let (agg_type, value_type) = aggregation_type(site, 2, 4, &group_type)?;
+
+    let output_type = Type::stream(Type::Tuple(vec![Type::String, value_type]));
+    Ok(Type::function(vec![Type::String, ts_fn_type, agg_type, group_type], output_type))
+}
+
+/// Type of the aggregation argument of `group_by` and `bucket_by_time`, along with the type of its result.
+/// Unlike other lambdas, the aggregation lambda takes a stream (the group) as parameter.
+fn aggregation_type(site: &mut CallSite, idx: usize, n_params: usize, group_type: &Type) -> Result<(Type, Type), Error> {
+    let agg_type =
+        match site.arguments.get_mut(idx) {
+            Some(Expr::Lambda(lambda)) if lambda.params.len() == 1 => {
+                let param = &lambda.params[0];
+                if let Some(annotation) = &param.annotation {
+                    if !annotation.typ.accepts(group_type) {
+                        return Err(Error::TypeMismatch {
+                            expected: annotation.typ.to_string(),
+                            found:    group_type.to_string(),
+                            err_pos:  annotation.position
+                        });
+                    }
+                }
+                site.env.var_types.insert(param.var.id, group_type.clone());
+                let return_type = lambda.body.typecheck(site.env)?;
+
+                let agg_type = Type::function(vec![group_type.clone()], return_type);
+                site.arg_types[idx] = Some(agg_type.clone());
+                agg_type
+            }
+            _ =>
+                match site.applied_type(idx, std::slice::from_ref(group_type))? {
+                    Some(agg_type) => agg_type,
+                    None => return Err(site.missing(n_params)),
+                },
+        };
+    let value_type =
+        match &agg_type {
+            Type::Function { parameters, return_type } if parameters.len() == 1 =>
+                return_type.as_ref().clone(),
+            _ =>
+                return Err(site.wrong_type(idx, format!("fn ({}) -> anything", group_type), &agg_type)),
+        };
+
+    // Groups are aggregated incrementally, which only works for some shapes of aggregations
+    if let Some(agg) = site.arguments.get(idx) {
+        if !is_aggregation(agg) {
+            return Err(Error::UnsupportedAggregation(agg.position()));
+        }
+    }
+    Ok((agg_type, value_type))
+}
+
+/// Whether an expression is a reducer, or a lambda that applies a reducer
+/// to a chain of `map`, `filter` and `filter_some` over its parameter
+fn is_aggregation(agg: &Expr) -> bool {
+    match agg {
+        Expr::Builtin(Builtin::Reduce(_), _) =>
+            true,
+        Expr::Lambda(lambda) =>
+            match lambda.body.as_ref() {
+                Expr::FunCall(FunCall { function, arguments }) if arguments.len() == 1 =>
+                    matches!(function.as_ref(), Expr::Builtin(Builtin::Reduce(_), _)) &&
+                    is_group_pipeline(&arguments[0], lambda.params[0].var.id),
+                _ =>
+                    false,
+            },
+        _ =>
+            false,
+    }
+}
+
+fn is_group_pipeline(expr: &Expr, group_var: usize) -> bool {
+    match expr {
+        Expr::Var(_, var) =>
+            var.id == group_var,
+        // The step functions are applied to items one by one, they can't use the whole group
+        Expr::FunCall(FunCall { function, arguments }) if arguments.len() == 2 =>
+            matches!(function.as_ref(), Expr::Builtin(Builtin::Map | Builtin::Filter, _)) &&
+            !mentions_var(&arguments[0], group_var) &&
+            is_group_pipeline(&arguments[1], group_var),
+        Expr::FunCall(FunCall { function, arguments }) if arguments.len() == 1 =>
+            matches!(function.as_ref(), Expr::Builtin(Builtin::FilterSome, _)) &&
+            is_group_pipeline(&arguments[0], group_var),
+        _ =>
+            false,
+    }
+}
+
+fn mentions_var(expr: &Expr, var_id: usize) -> bool {
+    match expr {
+        Expr::Var(_, var) =>
+            var.id == var_id,
+        Expr::FunCall(fcall) =>
+            mentions_var(&fcall.function, var_id) || fcall.arguments.iter().any(|arg| mentions_var(arg, var_id)),
+        Expr::Lambda(lambda) =>
+            mentions_var(&lambda.body, var_id),
+        Expr::Let(let_expr) =>
+            mentions_var(&let_expr.value, var_id) || mentions_var(&let_expr.body, var_id),
+        Expr::Builtin(..) | Expr::Literal(..) | Expr::UnresolvedIdentifier(_) | Expr::ReadVar(..) =>
+            false,
+    }
+}
+
+fn typecheck_top(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = applied_item_type(site, 1, 2, 3)?;
+    let (key_fn_type, _key_type) = item_fn_type(site, 1, &source_items)?;
+    let source_type = Type::stream(source_items);
+    Ok(Type::function(vec![Type::Int, key_fn_type, source_type.clone()], source_type))
+}
+
+fn typecheck_frequent(site: &mut CallSite) -> Result<Type, Error> {
+    let source_items = stream_arg_item(site, 1, 2)?;
+    let counted = Type::Tuple(vec![Type::Int, source_items.clone()]);
+    Ok(Type::function(vec![Type::Int, Type::stream(source_items)], Type::stream(counted)))
+}
+
+fn typecheck_tuple_elem(site: &mut CallSite, index: usize) -> Result<Type, Error> {
+    match site.require(0, 1)? {
+        Type::Tuple(elems) => {
+            let elem_type = elems[index].clone();
+            Ok(Type::function(vec![Type::Tuple(elems)], elem_type))
+        }
+        other =>
+            Err(site.wrong_type(0, "any tuple type".into(), &other)),
+    }
+}
+
+/// Signature of `fold` and `scan`.
+/// The accumulator starts with the type of the initial value, and gets promoted
+/// if the step function returns a wider numeric type (e.g. float for int).
+fn typecheck_fold(site: &mut CallSite, scan: bool) -> Result<Type, Error> {
+    let init_type = site.require(0, 3)?;
+    let source_items =
+        if site.arg_type(2)?.is_some() {
+            stream_arg_item(site, 2, 3)?
+        }
+        else {
+            // Partial application: the step function has to tell us the item type
+            match site.applied_type(1, std::slice::from_ref(&init_type))? {
+                Some(Type::Function { mut parameters, .. }) if parameters.len() == 2 => parameters.pop().unwrap(),
+                _ => return Err(site.missing(3)),
+            }
+        };
+
+    let mut acc_type = init_type.clone();
+    let step_type =
+        loop {
+            let param_types = [acc_type.clone(), source_items.clone()];
+            let step_type = site.applied_type(1, &param_types)?.unwrap();
+            let return_type =
+                match &step_type {
+                    Type::Function { parameters, return_type } if parameters.len() == 2 =>
+                        return_type.as_ref(),
+                    _ =>
+                        return Err(site.wrong_type(1, "a function of two arguments".into(), &step_type)),
+                };
+
+            if acc_type.accepts(return_type) {
+                break step_type;
+            }
+            if acc_type.is_numeric() && return_type.is_numeric() && site.arguments.len() > 1 {
+                // Typecheck the step function again, now that we know more about the accumulator
+                acc_type = Type::promote(&acc_type, return_type);
+                site.arg_types[1] = None;
+                continue;
+            }
+
+            let expected = format!("fn ({}, {}) -> {}", acc_type, source_items, acc_type);
+            return Err(site.label_streams(site.wrong_type(1, expected, &step_type)));
+        };
+
+    // Ints can be used where numbers are expected, but not where floats are
+    if init_type == Type::Int && acc_type == Type::Float && !site.arguments.is_empty() {
+        let init_pos = site.arguments[0].position();
+        let init = std::mem::replace(&mut site.arguments[0], Expr::Literal(Literal::Int(0), init_pos));
+        site.arguments[0] = FunCall::new_expr(Expr::Builtin(Builtin::ToFloat, init_pos), vec![init]);
+        site.arg_types[0] = Some(Type::Float);
+    }
+
+    let output_type = if scan { Type::stream(acc_type.clone()) } else { acc_type.clone() };
+    Ok(Type::function(vec![acc_type, step_type, Type::stream(source_items)], output_type))
+}
+
+/// Signature of a list literal, with one parameter per element.
+/// Mixing ints and floats gives a list of floats.
+fn typecheck_list(site: &mut CallSite) -> Result<Type, Error> {
+    let n_elems = site.arguments.len();
+    let mut elem_type = site.require(0, n_elems)?;
+    for idx in 1..n_elems {
+        let this_type = site.require(idx, n_elems)?;
+        if elem_type.accepts(&this_type) {
+            continue;
+        }
+        else if this_type.accepts(&elem_type) {
+            elem_type = this_type;
+        }
+        else if elem_type.is_numeric() && this_type.is_numeric() {
+            elem_type = Type::promote(&elem_type, &this_type);
+        }
+        else {
+            return Err(site.wrong_type(idx, elem_type.to_string(), &this_type));
+        }
+    }
+
+    // Ints can be used where numbers are expected, but not where floats are
+    if elem_type == Type::Float {
+        for idx in 0..n_elems {
+            if site.arg_types[idx] == Some(Type::Int) {
+                let elem_pos = site.arguments[idx].position();
+                let elem = std::mem::replace(&mut site.arguments[idx], Expr::Literal(Literal::Int(0), elem_pos));
+                site.arguments[idx] = FunCall::new_expr(Expr::Builtin(Builtin::ToFloat, elem_pos), vec![elem]);
+                site.arg_types[idx] = Some(Type::Float);
+            }
+        }
+    }
+
+    Ok(Type::function(vec![elem_type.clone(); n_elems], Type::list(elem_type)))
+}
+
+/// A single percentile, or (percentile, value, error bound) tuples for a list of percentiles
+fn typecheck_percentile(site: &mut CallSite, several: bool) -> Result<Type, Error> {
+    let percentile_type = site.require(0, 2)?;
+    let percentile_ok =
+        match &percentile_type {
+            Type::List(elem_type) if several => elem_type.is_numeric(),
+            other => !several && other.is_numeric(),
+        };
+    if !percentile_ok {
+        let expected = if several { "a list of numbers" } else { "any numeric type" };
+        return Err(site.wrong_type(0, expected.into(), &percentile_type));
+    }
+
+    let source_type = site.require(1, 2)?;
+    match source_type.stream_item() {
+        Some(item_type) if item_type.is_numeric() => {
+            let return_type =
+                if several { Type::stream(Type::Tuple(vec![Type::Float, Type::Float, Type::Float])) }
+                else { Type::maybe(Type::Float) };
+            Ok(Type::function(vec![percentile_type, source_type], return_type))
+        }
+        _ =>
+            Err(site.wrong_type(1, "a stream of any numeric type".into(), &source_type)),
+    }
+}
+
+fn typecheck_default(site: &mut CallSite) -> Result<Type, Error> {
+    // The optional value determines the type, unless it's not provided yet
+    let inner_type =
+        match site.arg_type(1)? {
+            Some(Type::Maybe(inner_type)) => *inner_type,
+            Some(other) => return Err(site.wrong_type(1, "any optional type".into(), &other)),
+            None => site.require(0, 2)?,
+        };
+
+    Ok(Type::function(vec![inner_type.clone(), Type::maybe(inner_type.clone())], inner_type))
+}
+
+fn typecheck_is_some(site: &mut CallSite) -> Result<Type, Error> {
+    match site.require(0, 1)? {
+        opt_type@Type::Maybe(_) =>
+            Ok(Type::function(vec![opt_type], Type::Bool)),
+        other =>
+            Err(site.wrong_type(0, "any optional type".into(), &other)),
+    }
 }
 
 /* Pretty printing */
@@ -275,8 +1272,22 @@ impl Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Type::String => write!(f, "string"),
+            Type::Int    => write!(f, "int"),
+            Type::Float  => write!(f, "float"),
             Type::Number => write!(f, "number"),
             Type::Bool   => write!(f, "bool"),
+            Type::List(elem) => write!(f, "list of {}", elem),
+            Type::Maybe(inner) => write!(f, "maybe {}", inner),
+            Type::Tuple(elems) => {
+                write!(f, "(")?;
+                for (idx, elem) in elems.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", elem)?;
+                }
+                write!(f, ")")
+            }
             Type::Stream(item) => write!(f, "stream of {}", item),
             Type::Function { parameters, return_type } => {
                 write!(f, "fn (")?;
@@ -5,36 +5,94 @@ use crate::compile::{ParsePos, Identifier};
 pub enum Error {
     EmptyProgram,
     TooManyCliArgs,
-    TooManyExprs(ParsePos),  // TODO remove
-    CantResolve(Identifier),
+    UnmatchedParen(ParsePos),
+    UnclosedParen(ParsePos),
+    ExpectedExpression(ParsePos),
+    CantResolve { idn: Identifier, suggestion: Option<String> },
     NotEnoughArguments { expected: usize, found: usize, err_pos: ParsePos },
     TooManyArguments { expected: usize, found: usize, err_pos: ParsePos },
     UnrecognizedToken(ParsePos),
     NotAFunction(ParsePos),
     WrongArgType { expected: String, found: String, err_pos: ParsePos },
     NonFormattable(String),
-    NotANumber { str_value: String, parse_err: std::num::ParseFloatError, err_pos: ParsePos },
+    NotANumber { str_value: String, target: &'static str, parse_err: String, err_pos: ParsePos },
+    IndexOutOfBounds { index: i64, len: usize, err_pos: ParsePos },
+    IntegerOverflow(ParsePos),
+    DivisionByZero(ParsePos),
+    UnexpectedToken(ParsePos),
+    ExpectedToken { expected: &'static str, err_pos: ParsePos },
+    UnknownType(ParsePos),
+    CannotInferType(Identifier),
+    InvalidParamType { found: String, err_pos: ParsePos },
+    TypeMismatch { expected: String, found: String, err_pos: ParsePos },
+    StreamVariableReused(Identifier),
+    UnknownCliOption(String),
+    NegativeCount { count: i64, err_pos: ParsePos },
+    OutputFailed(String),
+    InvalidCliOptionValue { option: &'static str, value: String },
+    TempFileFailed(String),
+    UnsupportedAggregation(ParsePos),
+    UnsupportedLineno(ParsePos),
+    EmptyWindow(ParsePos),
+    InvalidBuckets { reason: &'static str, err_pos: ParsePos },
+    InvalidPercentile { percentile: f64, err_pos: ParsePos },
+    ZeroStride(ParsePos),
+    InvalidProbability { probability: f64, err_pos: ParsePos },
+    InvalidDuration { duration: String, err_pos: ParsePos },
+    InvalidTimestamp { timestamp: String, err_pos: ParsePos },
+    FileOpenFailed { path: String, io_err: String, err_pos: ParsePos },
+    InputFailed(String),
+    // Any error, with secondary positions that help explaining it
+    Labelled { error: Box<Error>, labels: Vec<Label> },
+}
+
+/// A secondary position in the source, with an explanation
+pub struct Label {
+    pub position: ParsePos,
+    pub message:  String,
 }
 
 impl Error {
     pub fn format<W: io::Write>(&self, source: &str, buf: &mut W) -> io::Result<()> {
-        match self.position() {
-            Some(p) => {
-                writeln!(buf, "{}", source)?;
-                write_error_line(p, buf)?;
-            },
-            None => { },
+        if let Some(p) = self.position() {
+            writeln!(buf, "{}", source)?;
+            write_error_line(p, buf)?;
+
+            for label in self.labels() {
+                write_label_line(label, buf)?;
+            }
         }
 
         write!(buf, "pump: {}", self)
     }
 
+    pub fn with_label(self, position: ParsePos, message: String) -> Error {
+        let label = Label { position, message };
+        match self {
+            Error::Labelled { error, mut labels } => {
+                labels.push(label);
+                Error::Labelled { error, labels }
+            }
+            _ =>
+                Error::Labelled { error: Box::new(self), labels: vec![label] },
+        }
+    }
+
+    fn labels(&self) -> &[Label] {
+        match self {
+            Error::Labelled { labels, .. } => labels,
+            _ => &[],
+        }
+    }
+
     fn position(&self) -> Option<ParsePos> {
         match &self {
             Error::EmptyProgram => None,
             Error::TooManyCliArgs => None,
-            Error::TooManyExprs(err_pos) => Some(*err_pos),
-            Error::CantResolve(idn) => Some(idn.position),
+            Error::UnmatchedParen(err_pos) => Some(*err_pos),
+            Error::UnclosedParen(err_pos) => Some(*err_pos),
+            Error::ExpectedExpression(err_pos) => Some(*err_pos),
+            Error::CantResolve { idn, .. } => Some(idn.position),
             Error::NotEnoughArguments { err_pos, .. } => Some(*err_pos),
             Error::TooManyArguments { err_pos, .. } => Some(*err_pos),
             Error::UnrecognizedToken(err_pos) => Some(*err_pos),
@@ -42,6 +100,33 @@ impl Error {
             Error::WrongArgType { err_pos, .. } => Some(*err_pos),
             Error::NonFormattable(_) => None,
             Error::NotANumber { err_pos, .. } => Some(*err_pos),
+            Error::IndexOutOfBounds { err_pos, .. } => Some(*err_pos),
+            Error::IntegerOverflow(err_pos) => Some(*err_pos),
+            Error::DivisionByZero(err_pos) => Some(*err_pos),
+            Error::UnexpectedToken(err_pos) => Some(*err_pos),
+            Error::ExpectedToken { err_pos, .. } => Some(*err_pos),
+            Error::UnknownType(err_pos) => Some(*err_pos),
+            Error::CannotInferType(idn) => Some(idn.position),
+            Error::InvalidParamType { err_pos, .. } => Some(*err_pos),
+            Error::TypeMismatch { err_pos, .. } => Some(*err_pos),
+            Error::StreamVariableReused(idn) => Some(idn.position),
+            Error::UnknownCliOption(_) => None,
+            Error::NegativeCount { err_pos, .. } => Some(*err_pos),
+            Error::OutputFailed(_) => None,
+            Error::InvalidCliOptionValue { .. } => None,
+            Error::TempFileFailed(_) => None,
+            Error::UnsupportedAggregation(err_pos) => Some(*err_pos),
+            Error::UnsupportedLineno(err_pos) => Some(*err_pos),
+            Error::EmptyWindow(err_pos) => Some(*err_pos),
+            Error::InvalidBuckets { err_pos, .. } => Some(*err_pos),
+            Error::InvalidPercentile { err_pos, .. } => Some(*err_pos),
+            Error::ZeroStride(err_pos) => Some(*err_pos),
+            Error::InvalidProbability { err_pos, .. } => Some(*err_pos),
+            Error::InvalidDuration { err_pos, .. } => Some(*err_pos),
+            Error::InvalidTimestamp { err_pos, .. } => Some(*err_pos),
+            Error::FileOpenFailed { err_pos, .. } => Some(*err_pos),
+            Error::InputFailed(_) => None,
+            Error::Labelled { error, .. } => error.position(),
         }
     }
 }
@@ -51,6 +136,11 @@ fn write_error_line<W: io::Write>(err_pos: ParsePos, buf: &mut W) -> io::Result<
     writeln!(buf, "{}{}", str::repeat(" ", err_pos.start), str::repeat("^", err_pos.len))
 }
 
+fn write_label_line<W: io::Write>(label: &Label, buf: &mut W) -> io::Result<()> {
+    let label_pos = label.position;
+    writeln!(buf, "{}{} {}", str::repeat(" ", label_pos.start), str::repeat("-", label_pos.len), label.message)
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -58,10 +148,16 @@ impl Display for Error {
                 write!(f, "Program is empty. Provide at least one expression."),
             Error::TooManyCliArgs =>
                 write!(f, "Too many command line arguments"),
-            Error::TooManyExprs(_) =>
-                write!(f, "Too many expressions (we only support 1 right now)"),
-            Error::CantResolve(idn) =>
+            Error::UnmatchedParen(_) =>
+                write!(f, "Unmatched closing parenthesis"),
+            Error::UnclosedParen(_) =>
+                write!(f, "Unclosed parenthesis"),
+            Error::ExpectedExpression(_) =>
+                write!(f, "Expected an expression"),
+            Error::CantResolve { idn, suggestion: None } =>
                 write!(f, "Can't resolve identifier {:?}", idn.name),
+            Error::CantResolve { idn, suggestion: Some(suggestion) } =>
+                write!(f, "Can't resolve identifier {:?}, did you mean {:?}?", idn.name, suggestion),
             Error::NotEnoughArguments { expected, found, .. } =>
                 write!(f, "Not enough arguments in function call: expected {}, found {}", expected, found),
             Error::TooManyArguments { expected, found, .. } =>
@@ -74,8 +170,62 @@ impl Display for Error {
                 write!(f, "Wrong argument type in function call: expected {}, found {}", expected, found),
             Error::NonFormattable(type_str) =>
                 write!(f, "Top-level program type cannot be formatted: {}", type_str),
-            Error::NotANumber { str_value, parse_err, .. } =>
-                write!(f, "runtime value {:?} cannot be parsed as a number ({})", str_value, parse_err),
+            Error::NotANumber { str_value, target, parse_err, .. } =>
+                write!(f, "runtime value {:?} cannot be parsed as {} ({})", str_value, target, parse_err),
+            Error::IndexOutOfBounds { index, len, .. } =>
+                write!(f, "runtime index {} is out of bounds for a list of length {}", index, len),
+            Error::IntegerOverflow(_) =>
+                write!(f, "integer overflow in arithmetic operation"),
+            Error::DivisionByZero(_) =>
+                write!(f, "integer division by zero"),
+            Error::UnexpectedToken(_) =>
+                write!(f, "Unexpected token"),
+            Error::ExpectedToken { expected, .. } =>
+                write!(f, "Expected {}", expected),
+            Error::UnknownType(_) =>
+                write!(f, "Unknown type"),
+            Error::CannotInferType(idn) =>
+                write!(f, "Can't infer the type of {:?}, please add a type annotation", idn.name),
+            Error::InvalidParamType { found, .. } =>
+                write!(f, "Lambda parameters can't have type {}", found),
+            Error::TypeMismatch { expected, found, .. } =>
+                write!(f, "Type mismatch: annotated as {}, found {}", expected, found),
+            Error::StreamVariableReused(idn) =>
+                write!(f, "Stream variable {:?} can only be used once", idn.name),
+            Error::UnknownCliOption(opt) =>
+                write!(f, "Unknown command line option {:?}", opt),
+            Error::NegativeCount { count, .. } =>
+                write!(f, "runtime count {} must not be negative", count),
+            Error::OutputFailed(io_err) =>
+                write!(f, "can't write output: {}", io_err),
+            Error::InvalidCliOptionValue { option, value } =>
+                write!(f, "Invalid value {:?} for command line option {}", value, option),
+            Error::TempFileFailed(io_err) =>
+                write!(f, "can't use temporary file: {}", io_err),
+            Error::UnsupportedLineno(_) =>
+                write!(f, "lineno is only available in functions applied to the lines of stdin or a file, before any sort, tail or other buffering operator"),
+            Error::UnsupportedAggregation(_) =>
+                write!(f, "Unsupported aggregation: expected a reducer, possibly applied to map, filter and filter_some over the group"),
+            Error::EmptyWindow(_) =>
+                write!(f, "runtime window size must be at least 1"),
+            Error::InvalidBuckets { reason, .. } =>
+                write!(f, "runtime buckets are invalid: {}", reason),
+            Error::InvalidPercentile { percentile, .. } =>
+                write!(f, "runtime percentile {} must be between 0 and 100", percentile),
+            Error::ZeroStride(_) =>
+                write!(f, "runtime stride must be at least 1"),
+            Error::InvalidProbability { probability, .. } =>
+                write!(f, "runtime probability {} must be between 0 and 1", probability),
+            Error::InvalidDuration { duration, .. } =>
+                write!(f, "runtime duration {:?} is invalid: expected a positive number of s, m, h or d", duration),
+            Error::InvalidTimestamp { timestamp, .. } =>
+                write!(f, "runtime timestamp {:?} cannot be parsed", timestamp),
+            Error::FileOpenFailed { path, io_err, .. } =>
+                write!(f, "can't open file {:?}: {}", path, io_err),
+            Error::InputFailed(io_err) =>
+                write!(f, "can't read input: {}", io_err),
+            Error::Labelled { error, .. } =>
+                Display::fmt(error, f),
         }
     }
 }
\ No newline at end of file
@@ -6,11 +6,19 @@ use std::env;
 
 use error::Error;
 
+/// What to do with the program
+struct CliArgs {
+    program:   String,
+    // Only print the type of the program
+    type_only: bool,
+    settings:  runtime::Settings,
+}
+
 fn main() {
-    let pgm = retrieve_program();
-    match pgm {
-        Ok(source) => {
-            match submain(&source) {
+    let cli_args = parse_cli_args();
+    match cli_args {
+        Ok(CliArgs { program: source, type_only, settings }) => {
+            match submain(&source, type_only, settings) {
                 Ok(_) => (),
                 Err(e) => {
                     e.format(&source, &mut std::io::stderr()).unwrap();
@@ -27,17 +35,77 @@ fn main() {
     }
 }
 
-fn retrieve_program() -> Result<String, Error> {
-    let mut args: Vec<String> = env::args().skip(1).collect();
+fn parse_cli_args() -> Result<CliArgs, Error> {
+    let mut programs = Vec::new();
+    let mut type_only = false;
+    let mut settings = runtime::Settings::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        // Options with a value can be written "--opt value" or "--opt=value"
+        let (opt, inline_value) =
+            match arg.split_once('=') {
+                Some((opt, value)) if opt.starts_with("--") => (opt.to_string(), Some(value.to_string())),
+                _ => (arg.clone(), None),
+            };
+        let mut option_value = |option: &'static str| {
+            inline_value.clone()
+                .or_else(|| args.next())
+                .ok_or(Error::InvalidCliOptionValue { option, value: String::new() })
+        };
+
+        match opt.as_str() {
+            "--type" => type_only = true,
+            "--sort-mem" => {
+                let value = option_value("--sort-mem")?;
+                settings.sort_mem =
+                    parse_size(&value)
+                        .ok_or(Error::InvalidCliOptionValue { option: "--sort-mem", value })?;
+            }
+            "--percentile-mem" => {
+                let value = option_value("--percentile-mem")?;
+                settings.percentile_mem =
+                    parse_size(&value)
+                        .ok_or(Error::InvalidCliOptionValue { option: "--percentile-mem", value })?;
+            }
+            "--seed" => {
+                let value = option_value("--seed")?;
+                let seed = value.parse().map_err(|_| Error::InvalidCliOptionValue { option: "--seed", value })?;
+                settings.seed = Some(seed);
+            }
+            opt if opt.starts_with("--") => return Err(Error::UnknownCliOption(arg)),
+            _ => programs.push(arg),
+        }
+    }
 
-    match args.len() {
+    match programs.len() {
         0 => Err(Error::EmptyProgram),
-        1 => Ok(args.pop().unwrap()),
+        1 => Ok(CliArgs { program: programs.pop().unwrap(), type_only, settings }),
         _ => Err(Error::TooManyCliArgs),
     }
 }
 
-fn submain(pgm: &str) -> Result<(), Error> {
-    let valid_pgm = compile::compile(&pgm)?;
-    runtime::exec_and_print(valid_pgm)
+/// A number of bytes, with an optional K, M or G (binary) suffix
+fn parse_size(size: &str) -> Option<usize> {
+    let (digits, multiplier) =
+        match size.char_indices().last()? {
+            (idx, 'K' | 'k') => (&size[..idx], 1 << 10),
+            (idx, 'M' | 'm') => (&size[..idx], 1 << 20),
+            (idx, 'G' | 'g') => (&size[..idx], 1 << 30),
+            _ => (size, 1),
+        };
+
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+fn submain(pgm: &str, type_only: bool, settings: runtime::Settings) -> Result<(), Error> {
+    if type_only {
+        // Don't execute anything, this doesn't even need to be a valid top-level program
+        let pgm_type = compile::type_of(pgm)?;
+        println!("{}", pgm_type);
+        return Ok(());
+    }
+
+    let valid_pgm = compile::compile(pgm)?;
+    runtime::exec_and_print(valid_pgm, settings)
 }
\ No newline at end of file
@@ -1,36 +1,110 @@
 mod scalar;
 mod stream;
 
-use std::{cell::Cell, fmt::{Debug, Display}, rc::Rc};
+use std::{cell::{Cell, RefCell}, cmp::Ordering, fmt::{Debug, Display}, hash::{Hash, Hasher}, io::{self, Write}, rc::Rc, sync::OnceLock};
 
 use crate::error::Error;
-use crate::compile::Expr;
+use crate::compile::{Expr, FunCall, Program};
+use scalar::ExecScalar;
 
-pub fn exec_and_print(expr_tree: Expr) -> Result<(), Error> {
-    let mut exec_tree = stream::stream_from(expr_tree);
+/// Runtime settings, which can be changed from the command line
+pub struct Settings {
+    /// Approximate number of bytes that sorts can use before spilling to temporary files
+    pub sort_mem: usize,
+    /// Approximate number of bytes that percentiles can use before switching to a sketch
+    pub percentile_mem: usize,
+    /// Seed of the random sampling operators, or None for a different sample on every run
+    pub seed: Option<u64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { sort_mem: 256 * 1024 * 1024, percentile_mem: 8 * 1024 * 1024, seed: None }
+    }
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(Settings::default)
+}
+
+pub fn exec_and_print(program: Program, settings: Settings) -> Result<(), Error> {
+    // Note: the settings can only be set once, which is fine as we only run a single program
+    let _ = SETTINGS.set(settings);
+
+    let mut stdout = io::stdout().lock();
 
-    while let Some(rt_val) = exec_tree.next() {
+    if !program.is_stream {
+        // A single value, which typically needs to read the whole input first
+        let rt_val = scalar::scalar_from(program.expr_tree).eval()?;
+        print_line(&mut stdout, &rt_val)?;
+        return Ok(());
+    }
+
+    let exec_tree = stream::stream_from(program.expr_tree);
+    for rt_val in exec_tree {
         let line_to_print = rt_val?;
-        println!("{}", line_to_print.format());
+        if !print_line(&mut stdout, &line_to_print)? {
+            break;
+        }
     }
     Ok(())
 }
 
+/// Returns whether the output is still open
+fn print_line<W: Write>(out: &mut W, rt_val: &RtVal) -> Result<bool, Error> {
+    match writeln!(out, "{}", rt_val.format()) {
+        Ok(()) => Ok(true),
+        // Whoever reads our output is done with it (e.g. "pump ... | head"): stop quietly
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(false),
+        Err(e) => Err(Error::OutputFailed(e.to_string())),
+    }
+}
+
 /* RtVal */
 
 #[derive(Clone)]
 enum RtVal {
     String(String),
-    Number(Number),
-    Bool(bool)
+    Int(Int),
+    Float(Float),
+    Bool(bool),
+    List(Vec<RtVal>),
+    Maybe(Option<Box<RtVal>>),
+    Tuple(Vec<RtVal>),
 }
 
-type Number = f64;
+type Int = i64;
+type Float = f64;
 
 impl RtVal {
     fn str_ref(&self) -> Option<&str> {
         match self {
-            Self::String(s) => Some(&s),
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_int(&self) -> Option<Int> {
+        match self {
+            Self::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Any numeric value, promoted to a float if required
+    fn as_float(&self) -> Option<Float> {
+        match self {
+            Self::Int(n) => Some(*n as Float),
+            Self::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> Option<&[RtVal]> {
+        match self {
+            Self::List(l) => Some(l),
             _ => None,
         }
     }
@@ -42,11 +116,106 @@ impl RtVal {
         }
     }
 
+    fn as_maybe(&self) -> Option<Option<&RtVal>> {
+        match self {
+            Self::Maybe(opt) => Some(opt.as_deref()),
+            _ => None,
+        }
+    }
+
+    /// Total order used for sorting.
+    /// Ints and floats are compared by value, whatever their runtime type.
+    fn sort_cmp(&self, other: &RtVal) -> Ordering {
+        match (self, other) {
+            (Self::String(l), Self::String(r)) => l.cmp(r),
+            (Self::Int(l), Self::Int(r)) => l.cmp(r),
+            (Self::Bool(l), Self::Bool(r)) => l.cmp(r),
+            (Self::Int(_) | Self::Float(_), Self::Int(_) | Self::Float(_)) =>
+                self.as_float().unwrap().total_cmp(&other.as_float().unwrap()),
+            (Self::List(l), Self::List(r)) | (Self::Tuple(l), Self::Tuple(r)) =>
+                cmp_seq(l, r),
+            // Missing values come first
+            (Self::Maybe(l), Self::Maybe(r)) =>
+                match (l, r) {
+                    (None, None) => Ordering::Equal,
+                    (None, Some(_)) => Ordering::Less,
+                    (Some(_), None) => Ordering::Greater,
+                    (Some(l), Some(r)) => l.sort_cmp(r),
+                },
+            // Typechecking guarantees that we only compare values of the same type
+            _ => panic!("Can't compare {} and {}", self, other),
+        }
+    }
+
+    /// Rough estimate of the memory used by the value
+    fn approx_size(&self) -> usize {
+        let heap_size =
+            match self {
+                Self::String(s) => s.capacity(),
+                Self::List(l) | Self::Tuple(l) => l.iter().map(RtVal::approx_size).sum(),
+                Self::Maybe(Some(v)) => v.approx_size(),
+                _ => 0,
+            };
+        std::mem::size_of::<RtVal>() + heap_size
+    }
+
     fn format(&self) -> String {
         match self {
             Self::String(s) => s.clone(),
-            Self::Number(n) => n.to_string(),
+            Self::Int(n) => n.to_string(),
+            // Like float literals, with a fractional part or an exponent, so that they don't pass for ints
+            Self::Float(x) => format!("{:?}", x),
             Self::Bool(b) => b.to_string(),
+            Self::List(l) =>
+                l.iter()
+                    .map(RtVal::format)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            // Missing values are printed as empty strings
+            Self::Maybe(opt) =>
+                opt.as_ref()
+                    .map(|v| v.format())
+                    .unwrap_or_default(),
+            // Tuple elements are separated by tabs, which makes it easy to use cut and friends
+            Self::Tuple(elems) =>
+                elems.iter()
+                    .map(RtVal::format)
+                    .collect::<Vec<_>>()
+                    .join("\t"),
+        }
+    }
+}
+
+// Note: floats are compared by their bit pattern, so that values can be used as keys.
+// This means that NaN equals itself, but 0.0 and -0.0 are different.
+impl PartialEq for RtVal {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::String(l), Self::String(r)) => l == r,
+            (Self::Int(l), Self::Int(r)) => l == r,
+            (Self::Float(l), Self::Float(r)) => l.to_bits() == r.to_bits(),
+            (Self::Bool(l), Self::Bool(r)) => l == r,
+            (Self::List(l), Self::List(r)) => l == r,
+            (Self::Maybe(l), Self::Maybe(r)) => l == r,
+            (Self::Tuple(l), Self::Tuple(r)) => l == r,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for RtVal { }
+
+impl Hash for RtVal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::String(s) => s.hash(state),
+            Self::Int(n) => n.hash(state),
+            Self::Float(x) => x.to_bits().hash(state),
+            Self::Bool(b) => b.hash(state),
+            Self::List(l) => l.hash(state),
+            Self::Maybe(opt) => opt.hash(state),
+            Self::Tuple(elems) => elems.hash(state),
         }
     }
 }
@@ -55,8 +224,31 @@ impl Display for RtVal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::String(s) => Display::fmt(s, f),
-            Self::Number(n) => Display::fmt(n, f),
+            Self::Int(n) => Display::fmt(n, f),
+            Self::Float(x) => Debug::fmt(x, f),
             Self::Bool(b) => Display::fmt(b, f),
+            Self::List(l) => {
+                write!(f, "[")?;
+                for (idx, elem) in l.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    Display::fmt(elem, f)?;
+                }
+                write!(f, "]")
+            }
+            Self::Maybe(Some(v)) => Display::fmt(v, f),
+            Self::Maybe(None) => write!(f, "none"),
+            Self::Tuple(elems) => {
+                write!(f, "(")?;
+                for (idx, elem) in elems.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    Display::fmt(elem, f)?;
+                }
+                write!(f, ")")
+            }
         }
     }
 }
@@ -67,9 +259,15 @@ impl From<String> for RtVal {
     }
 }
 
-impl From<Number> for RtVal {
-    fn from(value: Number) -> Self {
-        Self::Number(value)
+impl From<Int> for RtVal {
+    fn from(value: Int) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<Float> for RtVal {
+    fn from(value: Float) -> Self {
+        Self::Float(value)
     }
 }
 
@@ -79,6 +277,28 @@ impl From<bool> for RtVal {
     }
 }
 
+impl From<Vec<RtVal>> for RtVal {
+    fn from(value: Vec<RtVal>) -> Self {
+        Self::List(value)
+    }
+}
+
+impl From<Option<RtVal>> for RtVal {
+    fn from(value: Option<RtVal>) -> Self {
+        Self::Maybe(value.map(Box::new))
+    }
+}
+
+fn cmp_seq(left: &[RtVal], right: &[RtVal]) -> Ordering {
+    for (l, r) in left.iter().zip(right) {
+        match l.sort_cmp(r) {
+            Ordering::Equal => continue,
+            unequal => return unequal,
+        }
+    }
+    left.len().cmp(&right.len())
+}
+
 /* StreamVar */
 
 /// A variable that acts as a channel, read and written for each
@@ -117,4 +337,92 @@ impl Debug for StreamVar {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "StreamVar({:?})", self.0.as_ptr())
     }
-}
\ No newline at end of file
+}
+
+/* LocalVar */
+
+/// A variable bound by a lambda parameter or a let expression.
+/// Unlike a StreamVar, its value can be read any number of times.
+#[derive(Clone, Default)]
+pub struct LocalVar(Rc<RefCell<Option<RtVal>>>);
+
+impl LocalVar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn set(&self, new_value: RtVal) {
+        self.0.replace(Some(new_value));
+    }
+
+    fn get(&self) -> RtVal {
+        // The binding lambda or let expression always sets the value before its body gets evaluated
+        self.0
+            .borrow()
+            .clone()
+            .unwrap()
+    }
+}
+
+impl Debug for LocalVar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LocalVar({:?})", self.0.as_ptr())
+    }
+}
+/* Bindings */
+
+/// Whether the expression binds local variables before evaluating a body,
+/// i.e. it's either a let expression or the application of a lambda
+fn is_binding(expr: &Expr) -> bool {
+    match expr {
+        Expr::Let(_) => true,
+        Expr::FunCall(fcall) => matches!(*fcall.function, Expr::Lambda(_) | Expr::Let(_)),
+        _ => false,
+    }
+}
+
+/// The variables bound by a binding expression, with the values they are bound to, and its body
+fn split_binding(expr: Expr) -> (Vec<(LocalVar, Expr)>, Expr) {
+    match expr {
+        Expr::Let(let_expr) => {
+            let binding = (let_expr.binding.var.value, *let_expr.value);
+            (vec![binding], *let_expr.body)
+        }
+        Expr::FunCall(fcall) => {
+            let mut arguments = fcall.arguments;
+            match *fcall.function {
+                Expr::Lambda(lambda) => {
+                    // Typechecking guarantees that lambdas are fully applied at runtime
+                    assert!(arguments.len() >= lambda.params.len());
+                    let extra_args = arguments.split_off(lambda.params.len());
+
+                    let bindings =
+                        lambda.params
+                            .into_iter()
+                            .map(|param| param.var.value)
+                            .zip(arguments)
+                            .collect();
+
+                    (bindings, apply_extra_args(*lambda.body, extra_args))
+                }
+                Expr::Let(let_expr) => {
+                    let binding = (let_expr.binding.var.value, *let_expr.value);
+                    (vec![binding], apply_extra_args(*let_expr.body, arguments))
+                }
+                other =>
+                    panic!("Not a binding expression: {:?}", other),
+            }
+        }
+        other =>
+            panic!("Not a binding expression: {:?}", other),
+    }
+}
+
+fn apply_extra_args(body: Expr, extra_args: Vec<Expr>) -> Expr {
+    if extra_args.is_empty() {
+        body
+    }
+    else {
+        FunCall::new_expr(body, extra_args)
+    }
+}
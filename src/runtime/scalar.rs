@@ -1,9 +1,12 @@
+pub(super) mod percentile;
+pub(super) mod reduce;
+
 use regex::Regex;
 
 use crate::error::Error;
-use crate::compile::{self, Builtin, Expr, ParsePos};
+use crate::compile::{self, ArithOp, Builtin, Expr, Literal, ParsePos};
 
-use super::{RtVal, StreamVar, Number};
+use super::{is_binding, split_binding, stream, Float, Int, LocalVar, RtVal, StreamVar};
 
 /// Runtime components that return scalar values
 pub trait ExecScalar {
@@ -15,7 +18,25 @@ pub enum ScalarNode {
     RegexMatch(RegexMatch),
     RegexSubst(RegexSubst),
     ReadStreamVar(ReadStreamVar),
-    ToNumber(ToNumber)
+    RegexCapture(RegexCapture),
+    ToNumber(ToNumber),
+    Arith(Arith),
+    Constant(Constant),
+    Split(Split),
+    Words(Words),
+    ListLit(ListLit),
+    At(At),
+    Len(Len),
+    Join(Join),
+    OrDefault(OrDefault),
+    IsSome(IsSome),
+    TupleElem(TupleElem),
+    Bind(Bind),
+    ReadLocal(ReadLocal),
+    Lineno,
+    Reduce(reduce::Reduce),
+    Fold(reduce::Fold),
+    Percentile(percentile::Percentile),
 }
 
 impl ExecScalar for ScalarNode {
@@ -24,7 +45,25 @@ impl ExecScalar for ScalarNode {
             Self::RegexMatch(r) => r.eval(),
             Self::RegexSubst(subst) => subst.eval(),
             Self::ReadStreamVar(rsv) => rsv.eval(),
+            Self::RegexCapture(c) => c.eval(),
             Self::ToNumber(n) => n.eval(),
+            Self::Arith(a) => a.eval(),
+            Self::Constant(c) => c.eval(),
+            Self::Split(split) => split.eval(),
+            Self::Words(words) => words.eval(),
+            Self::ListLit(list) => list.eval(),
+            Self::At(at) => at.eval(),
+            Self::Len(len) => len.eval(),
+            Self::Join(join) => join.eval(),
+            Self::OrDefault(d) => d.eval(),
+            Self::IsSome(is) => is.eval(),
+            Self::TupleElem(te) => te.eval(),
+            Self::Bind(bind) => bind.eval(),
+            Self::ReadLocal(rl) => rl.eval(),
+            Self::Lineno => Ok(RtVal::Int(stream::lineno())),
+            Self::Reduce(r) => r.eval(),
+            Self::Fold(f) => f.eval(),
+            Self::Percentile(p) => p.eval(),
         }
     }
 }
@@ -32,9 +71,17 @@ impl ExecScalar for ScalarNode {
 // TODO convert into From impl
 pub fn scalar_from(expr: Expr) -> ScalarNode {
     match expr {
+        binding if is_binding(&binding) => Bind::new_node(binding),
+
         Expr::FunCall(fcall) => scalar_fun_call(fcall),
 
-        Expr::ReadVar(var) => ReadStreamVar::new_node(var),
+        Expr::ReadVar(var, _pos) => ReadStreamVar::new_node(var),
+
+        Expr::Var(_idn, var) => ReadLocal::new_node(var.value),
+
+        Expr::Literal(lit, _pos) => Constant::new_node(lit),
+
+        Expr::Builtin(Builtin::Lineno, _pos) => ScalarNode::Lineno,
 
         // It's fine for us to panic here, as typechecking must have guaranteed that
         // we have what our caller expects here
@@ -56,11 +103,71 @@ fn scalar_fun_call(mut fcall: compile::FunCall) -> ScalarNode {
                     let single_arg = fcall.arguments.pop().unwrap();
                     RegexSubst::new_node(subst, single_arg)
                 }
-                Builtin::ToNumber => {
+                Builtin::RegexCapture(regex) => {
+                    assert_eq!(fcall.arguments.len(), 1);
+                    let single_arg = fcall.arguments.pop().unwrap();
+                    RegexCapture::new_node(regex, single_arg)
+                }
+                Builtin::ToNumber | Builtin::TryToNumber |
+                Builtin::ToInt | Builtin::TryToInt |
+                Builtin::ToFloat | Builtin::TryToFloat => {
                     assert_eq!(fcall.arguments.len(), 1);
                     let single_arg = fcall.arguments.pop().unwrap();
-                    ToNumber::new_node(single_arg, pos)
+                    let (target, fallible) =
+                        match b {
+                            Builtin::ToNumber    => (NumTarget::Number, false),
+                            Builtin::TryToNumber => (NumTarget::Number, true),
+                            Builtin::ToInt       => (NumTarget::Int, false),
+                            Builtin::TryToInt    => (NumTarget::Int, true),
+                            Builtin::ToFloat     => (NumTarget::Float, false),
+                            _                    => (NumTarget::Float, true),
+                        };
+                    ToNumber::new_node(single_arg, target, fallible, pos)
                 }
+                Builtin::Arith(op) =>
+                    Arith::new_node(op, fcall.arguments, pos),
+                Builtin::Split =>
+                    Split::new_node(fcall.arguments),
+                Builtin::Words => {
+                    assert_eq!(fcall.arguments.len(), 1);
+                    let single_arg = fcall.arguments.pop().unwrap();
+                    Words::new_node(single_arg)
+                }
+                Builtin::List =>
+                    ListLit::new_node(fcall.arguments),
+                Builtin::At =>
+                    At::new_node(fcall.arguments, false, pos),
+                Builtin::TryAt =>
+                    At::new_node(fcall.arguments, true, pos),
+                Builtin::Len => {
+                    assert_eq!(fcall.arguments.len(), 1);
+                    let single_arg = fcall.arguments.pop().unwrap();
+                    Len::new_node(single_arg)
+                }
+                Builtin::Join =>
+                    Join::new_node(fcall.arguments),
+                Builtin::Default =>
+                    OrDefault::new_node(fcall.arguments),
+                Builtin::IsSome => {
+                    assert_eq!(fcall.arguments.len(), 1);
+                    let single_arg = fcall.arguments.pop().unwrap();
+                    IsSome::new_node(single_arg)
+                }
+                Builtin::First | Builtin::Second => {
+                    assert_eq!(fcall.arguments.len(), 1);
+                    let single_arg = fcall.arguments.pop().unwrap();
+                    let index = if let Builtin::First = b { 0 } else { 1 };
+                    TupleElem::new_node(single_arg, index)
+                }
+                Builtin::Reduce(op) => {
+                    assert_eq!(fcall.arguments.len(), 1);
+                    let single_arg = fcall.arguments.pop().unwrap();
+                    reduce::Reduce::new_node(op, single_arg, pos)
+                }
+                Builtin::Fold =>
+                    reduce::Fold::new_node(fcall.arguments),
+                Builtin::Percentile =>
+                    percentile::Percentile::new_node(fcall.arguments),
                 _ => panic!("Not a scalar builtin: {:?}", b),
             }
         }
@@ -87,12 +194,44 @@ impl RegexMatch {
 impl ExecScalar for RegexMatch {
     fn eval(&mut self) -> Result<RtVal, Error> {
         let input = self.argument.eval()?;
-        let is_match = self.regex.is_match(&input.str_ref().unwrap());
+        let is_match = self.regex.is_match(input.str_ref().unwrap());
         let rt_val = is_match.into();
         Ok(rt_val)
     }
 }
 
+/* RegexCapture */
+
+struct RegexCapture {
+    regex:    Regex,
+    argument: Box<ScalarNode>,
+}
+
+impl RegexCapture {
+    fn new_node(regex: Regex, arg: Expr) -> ScalarNode {
+        let rt_arg = scalar_from(arg);
+        let argument = Box::new(rt_arg);
+        let me = RegexCapture { regex, argument };
+        ScalarNode::RegexCapture(me)
+    }
+}
+
+impl ExecScalar for RegexCapture {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        let input = self.argument.eval()?;
+
+        // Capture the first group if there is one, otherwise the whole match
+        let group_idx = if self.regex.captures_len() > 1 { 1 } else { 0 };
+        let captured =
+            self.regex
+                .captures(input.str_ref().unwrap())
+                .and_then(|rec| rec.get(group_idx))
+                .map(|m| RtVal::from(String::from(m.as_str())));
+
+        Ok(captured.into())
+    }
+}
+
 /* RegexSubst */
 
 struct RegexSubst {
@@ -101,9 +240,9 @@ struct RegexSubst {
     argument: Box<ScalarNode>,
 }
 
-use std::cell::LazyCell;
-const REGEX_GROUP_ID: LazyCell<regex::Regex> =
-    LazyCell::new(|| regex::Regex::new(r"\\(\d)").unwrap());
+use std::sync::LazyLock;
+static REGEX_GROUP_ID: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"\\(\d)").unwrap());
 
 impl RegexSubst {
     fn new_node(subst: compile::RegexSubst, arg: Expr) -> ScalarNode {
@@ -146,38 +285,500 @@ impl ExecScalar for RegexSubst {
 
 /* ToNumber */
 
+#[derive(Clone, Copy)]
+enum NumTarget {
+    // Int if possible, float otherwise
+    Number,
+    Int,
+    Float,
+}
+
 struct ToNumber {
     argument: Box<ScalarNode>,
+    target:   NumTarget,
+    // The "try" variant returns an optional value instead of failing
+    fallible: bool,
     src_pos:  ParsePos,
 }
 
 impl ToNumber {
-    fn new_node(arg: Expr, to_num_pos: ParsePos) -> ScalarNode {
+    fn new_node(arg: Expr, target: NumTarget, fallible: bool, to_num_pos: ParsePos) -> ScalarNode {
         let rt_arg = scalar_from(arg);
         let argument = Box::new(rt_arg);
 
-        let me = ToNumber { argument, src_pos: to_num_pos };
+        let me = ToNumber { argument, target, fallible, src_pos: to_num_pos };
         ScalarNode::ToNumber(me)
     }
 }
 
+impl NumTarget {
+    fn parse(self, str_input: &str) -> Result<RtVal, String> {
+        use std::str::FromStr;
+        let parse_int = || Int::from_str(str_input).map(RtVal::from);
+        // Rust also accepts "nan", "inf" and "infinity", and overflows to infinity: none of them is data
+        let parse_float = || match Float::from_str(str_input) {
+            Ok(x) if !x.is_finite() => Err("not a finite number".to_string()),
+            res => res.map(RtVal::from).map_err(|e| e.to_string()),
+        };
+
+        match self {
+            NumTarget::Number =>
+                parse_int().or_else(|_| parse_float()),
+            NumTarget::Int =>
+                parse_int().map_err(|e| e.to_string()),
+            NumTarget::Float =>
+                parse_float(),
+        }
+    }
+
+    fn convert(self, num_input: &RtVal) -> Result<RtVal, String> {
+        match (self, num_input) {
+            (NumTarget::Number, _) | (NumTarget::Int, RtVal::Int(_)) =>
+                Ok(num_input.clone()),
+            (NumTarget::Float, _) =>
+                Ok(num_input.as_float().unwrap().into()),
+            (NumTarget::Int, _) => {
+                // Floats are truncated towards zero
+                let x = num_input.as_float().unwrap();
+                // Note: i64::MAX is not representable as a float, but 2^63 is
+                if x.is_finite() && x >= Int::MIN as Float && x < -(Int::MIN as Float) {
+                    Ok((x.trunc() as Int).into())
+                }
+                else {
+                    Err("out of the range of ints".into())
+                }
+            }
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            NumTarget::Number => "a number",
+            NumTarget::Int    => "an int",
+            NumTarget::Float  => "a float",
+        }
+    }
+}
+
 impl ExecScalar for ToNumber {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        let input = self.argument.eval()?;
+
+        let conversion =
+            match &input {
+                RtVal::String(str_input) => self.target.parse(str_input),
+                _ => self.target.convert(&input),
+            };
+
+        if self.fallible {
+            let rt_val = conversion.ok().into();
+            return Ok(rt_val);
+        }
+
+        conversion
+            .map_err(|parse_err|
+                Error::NotANumber {
+                    str_value: input.format(),
+                    target: self.target.description(),
+                    parse_err,
+                    err_pos: self.src_pos
+                })
+    }
+}
+
+/* Arith */
+
+struct Arith {
+    op:      ArithOp,
+    left:    Box<ScalarNode>,
+    right:   Box<ScalarNode>,
+    src_pos: ParsePos,
+}
+
+impl Arith {
+    fn new_node(op: ArithOp, arguments: Vec<Expr>, op_pos: ParsePos) -> ScalarNode {
+        let mut args_iter = arguments.into_iter();
+        let left = scalar_from(args_iter.next().unwrap());
+        let right = scalar_from(args_iter.next().unwrap());
+
+        let me = Arith { op, left: Box::new(left), right: Box::new(right), src_pos: op_pos };
+        ScalarNode::Arith(me)
+    }
+
+    fn eval_int(&self, x: Int, y: Int) -> Result<RtVal, Error> {
+        if y == 0 && matches!(self.op, ArithOp::Div | ArithOp::Mod) {
+            return Err(Error::DivisionByZero(self.src_pos));
+        }
+
+        let checked_res =
+            match self.op {
+                ArithOp::Add => x.checked_add(y),
+                ArithOp::Sub => x.checked_sub(y),
+                ArithOp::Mul => x.checked_mul(y),
+                ArithOp::Div => x.checked_div(y),
+                ArithOp::Mod => x.checked_rem(y),
+            };
+
+        match checked_res {
+            Some(n) => Ok(n.into()),
+            None => Err(Error::IntegerOverflow(self.src_pos)),
+        }
+    }
+
+    fn eval_float(&self, x: Float, y: Float) -> RtVal {
+        let res =
+            match self.op {
+                ArithOp::Add => x + y,
+                ArithOp::Sub => x - y,
+                ArithOp::Mul => x * y,
+                ArithOp::Div => x / y,
+                ArithOp::Mod => x % y,
+            };
+        res.into()
+    }
+}
+
+impl ExecScalar for Arith {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        let left = self.left.eval()?;
+        let right = self.right.eval()?;
+
+        // Same promotion rules as in the typechecker
+        match (&left, &right) {
+            (RtVal::Int(x), RtVal::Int(y)) =>
+                self.eval_int(*x, *y),
+            _ =>
+                Ok(self.eval_float(left.as_float().unwrap(), right.as_float().unwrap())),
+        }
+    }
+}
+
+/* Constant */
+
+struct Constant {
+    value: RtVal,
+}
+
+impl Constant {
+    fn new_node(lit: Literal) -> ScalarNode {
+        let value =
+            match lit {
+                Literal::String(s) => s.into(),
+                Literal::Int(n) => n.into(),
+                Literal::Float(x) => x.into(),
+            };
+
+        ScalarNode::Constant(Constant { value })
+    }
+}
+
+impl ExecScalar for Constant {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        Ok(self.value.clone())
+    }
+}
+
+/* Split */
+
+enum Separator {
+    String(Box<ScalarNode>),
+    Regex(Regex),
+}
+
+struct Split {
+    separator: Separator,
+    argument:  Box<ScalarNode>,
+}
+
+impl Split {
+    fn new_node(arguments: Vec<Expr>) -> ScalarNode {
+        let mut args_iter = arguments.into_iter();
+        let separator_arg = args_iter.next().unwrap();
+        let split_arg = args_iter.next().unwrap();
+
+        // Typechecking guarantees that a regex separator is a regex literal
+        let separator =
+            match separator_arg {
+                Expr::Builtin(Builtin::RegexMatch(regex), _pos) =>
+                    Separator::Regex(regex),
+                _ =>
+                    Separator::String(Box::new(scalar_from(separator_arg))),
+            };
+
+        let argument = Box::new(scalar_from(split_arg));
+
+        ScalarNode::Split(Split { separator, argument })
+    }
+}
+
+impl ExecScalar for Split {
     fn eval(&mut self) -> Result<RtVal, Error> {
         let input = self.argument.eval()?;
         let str_input = input.str_ref().unwrap();
 
-        use std::str::FromStr;
-        let num_value =
-            Number::from_str(str_input)
-                .map_err(|parse_err|
-                    Error::NotANumber {
-                        str_value: str_input.into(),
-                        parse_err,
-                        err_pos: self.src_pos
-                    })?;
-
-        let rt_val = num_value.into();
-        Ok(rt_val)
+        let pieces: Vec<RtVal> =
+            match &mut self.separator {
+                Separator::String(sep_node) => {
+                    let sep = sep_node.eval()?;
+                    str_input
+                        .split(sep.str_ref().unwrap())
+                        .map(|piece| String::from(piece).into())
+                        .collect()
+                }
+                Separator::Regex(regex) =>
+                    regex
+                        .split(str_input)
+                        .map(|piece| String::from(piece).into())
+                        .collect(),
+            };
+
+        Ok(pieces.into())
+    }
+}
+
+/* Words */
+
+/// Splits on whitespace, without empty pieces
+struct Words {
+    argument: Box<ScalarNode>,
+}
+
+impl Words {
+    fn new_node(arg: Expr) -> ScalarNode {
+        let argument = Box::new(scalar_from(arg));
+        ScalarNode::Words(Words { argument })
+    }
+}
+
+impl ExecScalar for Words {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        let input = self.argument.eval()?;
+        let words: Vec<RtVal> =
+            input.str_ref().unwrap()
+                .split_whitespace()
+                .map(|word| String::from(word).into())
+                .collect();
+        Ok(words.into())
+    }
+}
+
+/* ListLit */
+
+struct ListLit {
+    elements: Vec<ScalarNode>,
+}
+
+impl ListLit {
+    fn new_node(arguments: Vec<Expr>) -> ScalarNode {
+        let elements = arguments.into_iter().map(scalar_from).collect();
+        ScalarNode::ListLit(ListLit { elements })
+    }
+}
+
+impl ExecScalar for ListLit {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        let values: Result<Vec<RtVal>, Error> =
+            self.elements.iter_mut()
+                .map(|elem| elem.eval())
+                .collect();
+        Ok(values?.into())
+    }
+}
+
+/* At */
+
+struct At {
+    index:    Box<ScalarNode>,
+    list:     Box<ScalarNode>,
+    // The "try" variant returns an optional value instead of failing
+    fallible: bool,
+    src_pos:  ParsePos,
+}
+
+impl At {
+    fn new_node(arguments: Vec<Expr>, fallible: bool, at_pos: ParsePos) -> ScalarNode {
+        let mut args_iter = arguments.into_iter();
+        let index = scalar_from(args_iter.next().unwrap());
+        let list = scalar_from(args_iter.next().unwrap());
+
+        let me = At { index: Box::new(index), list: Box::new(list), fallible, src_pos: at_pos };
+        ScalarNode::At(me)
+    }
+}
+
+/// Resolve a possibly negative index (counting from the end) into a list position
+fn list_position(index: Int, len: usize) -> Option<usize> {
+    let from_start =
+        if index < 0 {
+            len as Int + index
+        }
+        else {
+            index
+        };
+
+    if from_start >= 0 && from_start < len as Int {
+        Some(from_start as usize)
+    }
+    else {
+        None
+    }
+}
+
+impl ExecScalar for At {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        let index = self.index.eval()?.as_int().unwrap();
+        let list_val = self.list.eval()?;
+        let list = list_val.as_list().unwrap();
+
+        let elem = list_position(index, list.len()).map(|pos| list[pos].clone());
+
+        if self.fallible {
+            return Ok(elem.into());
+        }
+
+        match elem {
+            Some(v) =>
+                Ok(v),
+            None =>
+                Err(Error::IndexOutOfBounds { index, len: list.len(), err_pos: self.src_pos }),
+        }
+    }
+}
+
+/* Len */
+
+struct Len {
+    argument: Box<ScalarNode>,
+}
+
+impl Len {
+    fn new_node(arg: Expr) -> ScalarNode {
+        let argument = Box::new(scalar_from(arg));
+        ScalarNode::Len(Len { argument })
+    }
+}
+
+impl ExecScalar for Len {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        let input = self.argument.eval()?;
+
+        let len =
+            match &input {
+                RtVal::List(l) => l.len(),
+                // Note: we count characters, not bytes
+                RtVal::String(s) => s.chars().count(),
+                _ => panic!("Len::eval: not a list or a string"),
+            };
+
+        Ok((len as Int).into())
+    }
+}
+
+/* Join */
+
+struct Join {
+    separator: Box<ScalarNode>,
+    list:      Box<ScalarNode>,
+}
+
+impl Join {
+    fn new_node(arguments: Vec<Expr>) -> ScalarNode {
+        let mut args_iter = arguments.into_iter();
+        let separator = scalar_from(args_iter.next().unwrap());
+        let list = scalar_from(args_iter.next().unwrap());
+
+        let me = Join { separator: Box::new(separator), list: Box::new(list) };
+        ScalarNode::Join(me)
+    }
+}
+
+impl ExecScalar for Join {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        let separator = self.separator.eval()?;
+        let list_val = self.list.eval()?;
+
+        let joined =
+            list_val.as_list()
+                .unwrap()
+                .iter()
+                .map(RtVal::format)
+                .collect::<Vec<_>>()
+                .join(separator.str_ref().unwrap());
+
+        Ok(joined.into())
+    }
+}
+
+/* OrDefault */
+
+struct OrDefault {
+    default_value: Box<ScalarNode>,
+    argument:      Box<ScalarNode>,
+}
+
+impl OrDefault {
+    fn new_node(arguments: Vec<Expr>) -> ScalarNode {
+        let mut args_iter = arguments.into_iter();
+        let default_value = scalar_from(args_iter.next().unwrap());
+        let argument = scalar_from(args_iter.next().unwrap());
+
+        let me = OrDefault { default_value: Box::new(default_value), argument: Box::new(argument) };
+        ScalarNode::OrDefault(me)
+    }
+}
+
+impl ExecScalar for OrDefault {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        match self.argument.eval()? {
+            RtVal::Maybe(Some(v)) => Ok(*v),
+            // Note: the default value is only evaluated when needed
+            RtVal::Maybe(None) => self.default_value.eval(),
+            _ => panic!("OrDefault::eval: not an optional value"),
+        }
+    }
+}
+
+/* IsSome */
+
+struct IsSome {
+    argument: Box<ScalarNode>,
+}
+
+impl IsSome {
+    fn new_node(arg: Expr) -> ScalarNode {
+        let argument = Box::new(scalar_from(arg));
+        ScalarNode::IsSome(IsSome { argument })
+    }
+}
+
+impl ExecScalar for IsSome {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        let input = self.argument.eval()?;
+        let is_some = input.as_maybe().unwrap().is_some();
+        Ok(is_some.into())
+    }
+}
+
+/* TupleElem */
+
+struct TupleElem {
+    argument: Box<ScalarNode>,
+    index:    usize,
+}
+
+impl TupleElem {
+    fn new_node(arg: Expr, index: usize) -> ScalarNode {
+        let argument = Box::new(scalar_from(arg));
+        ScalarNode::TupleElem(TupleElem { argument, index })
+    }
+}
+
+impl ExecScalar for TupleElem {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        match self.argument.eval()? {
+            RtVal::Tuple(mut elems) => Ok(elems.swap_remove(self.index)),
+            _ => panic!("TupleElem: not a tuple"),
+        }
     }
 }
 
@@ -198,4 +799,59 @@ impl ExecScalar for ReadStreamVar {
         let var_content = self.var.read().unwrap();
         Ok(var_content)
     }
-}
\ No newline at end of file
+}
+/* Bind */
+
+/// Evaluates a body once its local variables are set
+struct Bind {
+    bindings: Vec<(LocalVar, ScalarNode)>,
+    body:     Box<ScalarNode>,
+}
+
+impl Bind {
+    fn new_node(binding_expr: Expr) -> ScalarNode {
+        let (bindings, body) = split_binding(binding_expr);
+
+        let bindings =
+            bindings.into_iter()
+                .map(|(var, value)| (var, scalar_from(value)))
+                .collect();
+        let body = Box::new(scalar_from(body));
+
+        ScalarNode::Bind(Bind { bindings, body })
+    }
+}
+
+impl ExecScalar for Bind {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        // All the values are computed before any variable is set,
+        // as they can't refer to each other
+        let mut values = Vec::with_capacity(self.bindings.len());
+        for (_var, value) in self.bindings.iter_mut() {
+            values.push(value.eval()?);
+        }
+        for ((var, _value), rt_val) in self.bindings.iter().zip(values) {
+            var.set(rt_val);
+        }
+
+        self.body.eval()
+    }
+}
+
+/* ReadLocal */
+
+struct ReadLocal {
+    var: LocalVar,
+}
+
+impl ReadLocal {
+    fn new_node(var: LocalVar) -> ScalarNode {
+        ScalarNode::ReadLocal(ReadLocal { var })
+    }
+}
+
+impl ExecScalar for ReadLocal {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        Ok(self.var.get())
+    }
+}
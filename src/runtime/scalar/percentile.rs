@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+
+use crate::{compile::{Expr, ParsePos, Position}, error::Error};
+
+use super::{scalar_from, ExecScalar, ScalarNode};
+use super::super::{settings, stream::{stream_from, StreamNode}, Float, RtVal};
+
+// Once the values don't fit in memory, percentiles are within 1% of the exact values
+const RELATIVE_ACCURACY: Float = 0.01;
+
+/* Percentile */
+
+/// A single percentile of a stream, or nothing for an empty stream
+pub(super) struct Percentile {
+    percentile: Box<ScalarNode>,
+    src_pos:    ParsePos,
+    stream:     Box<StreamNode>,
+    // The stream can only be consumed once
+    result:     Option<RtVal>,
+}
+
+impl Percentile {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> ScalarNode {
+        let mut args_iter = arguments.into_iter();
+        let percentile_arg = args_iter.next().unwrap();
+        let src_pos = percentile_arg.position();
+        let data_source = args_iter.next().unwrap();
+
+        let percentile = Percentile {
+            percentile: Box::new(scalar_from(percentile_arg)),
+            src_pos,
+            stream: Box::new(stream_from(data_source)),
+            result: None,
+        };
+        ScalarNode::Percentile(percentile)
+    }
+
+    fn percentile(&mut self) -> Result<RtVal, Error> {
+        let percentile = self.percentile.eval()?.as_float().unwrap();
+        check_percentile(percentile, self.src_pos)?;
+
+        let mut quantiles = Quantiles::new();
+        for rt_val in self.stream.as_mut() {
+            quantiles.add(rt_val?.as_float().unwrap());
+        }
+
+        let value = quantiles.quantile(percentile).map(|(value, _error)| RtVal::Float(value));
+        Ok(value.into())
+    }
+}
+
+impl ExecScalar for Percentile {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        if self.result.is_none() {
+            self.result = Some(self.percentile()?);
+        }
+        Ok(self.result.clone().unwrap())
+    }
+}
+
+pub fn check_percentile(percentile: Float, src_pos: ParsePos) -> Result<(), Error> {
+    if (0.0..=100.0).contains(&percentile) {
+        Ok(())
+    }
+    else {
+        Err(Error::InvalidPercentile { percentile, err_pos: src_pos })
+    }
+}
+
+/* Quantiles */
+
+/// Quantile estimation, exact until there are too many values to keep in memory
+pub enum Quantiles {
+    Exact { values: Vec<Float>, sorted: bool, limit: usize },
+    Sketch(Sketch),
+}
+
+impl Quantiles {
+    pub fn new() -> Self {
+        let limit = settings().percentile_mem / std::mem::size_of::<Float>();
+        Quantiles::Exact { values: Vec::new(), sorted: true, limit }
+    }
+
+    pub fn add(&mut self, x: Float) {
+        // NaNs have no rank
+        if x.is_nan() {
+            return;
+        }
+
+        match self {
+            Quantiles::Exact { values, sorted, limit } => {
+                values.push(x);
+                *sorted = false;
+                if values.len() > *limit {
+                    let mut sketch = Sketch::new(RELATIVE_ACCURACY);
+                    values.iter().for_each(|x| sketch.add(*x));
+                    *self = Quantiles::Sketch(sketch);
+                }
+            }
+            Quantiles::Sketch(sketch) =>
+                sketch.add(x),
+        }
+    }
+
+    /// The percentile (0 to 100) with linear interpolation between the closest ranks, along with
+    /// a bound of its absolute error: 0 when exact. None if there are no values.
+    pub fn quantile(&mut self, percentile: Float) -> Option<(Float, Float)> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+
+        // Dividing last keeps the weight exact for round percentiles (0.1 rather than 0.0999...)
+        let scaled_rank = percentile * (count - 1) as Float;
+        let lower = (scaled_rank / 100.0).floor() as usize;
+        let upper = (scaled_rank / 100.0).ceil() as usize;
+        let weight = (scaled_rank - lower as Float * 100.0) / 100.0;
+
+        let ((lower_val, upper_val), error) =
+            match self {
+                Quantiles::Exact { values, sorted, .. } => {
+                    if !*sorted {
+                        values.sort_by(Float::total_cmp);
+                        *sorted = true;
+                    }
+                    ((values[lower], values[upper]), 0.0)
+                }
+                Quantiles::Sketch(sketch) => {
+                    let (lower_val, upper_val) = (sketch.at_rank(lower), sketch.at_rank(upper));
+                    // Both are within the relative accuracy of the exact values, so is the interpolation
+                    let error = lower_val.abs().max(upper_val.abs()) * sketch.accuracy / (1.0 - sketch.accuracy);
+                    ((lower_val, upper_val), error)
+                }
+            };
+
+        let value = if lower == upper { lower_val } else { lower_val + weight * (upper_val - lower_val) };
+        Some((value, error))
+    }
+
+    fn count(&self) -> u64 {
+        match self {
+            Quantiles::Exact { values, .. } => values.len() as u64,
+            Quantiles::Sketch(sketch) => sketch.count,
+        }
+    }
+}
+
+/* Sketch */
+
+/// Logarithmic buckets, as in DDSketch: any value in a bucket is within
+/// the relative accuracy of the bucket's representative value.
+/// Memory only depends on the range of magnitudes, not on the number of values.
+pub struct Sketch {
+    accuracy: Float,
+    // Bucket i holds the magnitudes between gamma^(i-1) (excluded) and gamma^i
+    gamma_ln: Float,
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zeros:    u64,
+    count:    u64,
+}
+
+impl Sketch {
+    fn new(accuracy: Float) -> Self {
+        let gamma = (1.0 + accuracy) / (1.0 - accuracy);
+        Sketch { accuracy, gamma_ln: gamma.ln(), positive: BTreeMap::new(), negative: BTreeMap::new(), zeros: 0, count: 0 }
+    }
+
+    fn add(&mut self, x: Float) {
+        // Infinities end up in the bucket of the largest floats
+        let magnitude = x.abs().min(Float::MAX);
+        if magnitude < Float::MIN_POSITIVE {
+            self.zeros += 1;
+        }
+        else {
+            let bucket = (magnitude.ln() / self.gamma_ln).ceil() as i32;
+            let store = if x > 0.0 { &mut self.positive } else { &mut self.negative };
+            *store.entry(bucket).or_default() += 1;
+        }
+        self.count += 1;
+    }
+
+    /// The (approximate) value with the given rank, starting from 0
+    fn at_rank(&self, rank: usize) -> Float {
+        let rank = rank as u64;
+        let mut seen = 0;
+
+        // From the most negative values to the most positive ones
+        for (bucket, count) in self.negative.iter().rev() {
+            seen += count;
+            if seen > rank {
+                return -self.representative(*bucket);
+            }
+        }
+        seen += self.zeros;
+        if seen > rank {
+            return 0.0;
+        }
+        for (bucket, count) in &self.positive {
+            seen += count;
+            if seen > rank {
+                return self.representative(*bucket);
+            }
+        }
+        unreachable!("Rank {} beyond the sketch's {} values", rank, self.count)
+    }
+
+    fn representative(&self, bucket: i32) -> Float {
+        // The value with the same relative distance to both ends of the bucket
+        let gamma = self.gamma_ln.exp();
+        (bucket as Float * self.gamma_ln).exp() * 2.0 / (gamma + 1.0)
+    }
+}
@@ -0,0 +1,142 @@
+use std::cmp::Ordering;
+
+use crate::{compile::{Expr, ParsePos, ReduceOp}, error::Error};
+
+use super::{ExecScalar, ScalarNode};
+use super::super::{stream::{stream_from, StreamNode, StreamScan}, Float, Int, RtVal};
+
+/// Turns a whole stream into a single value
+pub(super) struct Reduce {
+    op:      ReduceOp,
+    stream:  Box<StreamNode>,
+    src_pos: ParsePos,
+    // The stream can only be consumed once
+    result:  Option<RtVal>,
+}
+
+impl Reduce {
+    pub(super) fn new_node(op: ReduceOp, data_source: Expr, op_pos: ParsePos) -> ScalarNode {
+        let reduce = Reduce { op, stream: Box::new(stream_from(data_source)), src_pos: op_pos, result: None };
+        ScalarNode::Reduce(reduce)
+    }
+
+    fn reduce(&mut self) -> Result<RtVal, Error> {
+        let mut accumulator = Accumulator::new(self.op, self.src_pos);
+        for rt_val in self.stream.as_mut() {
+            accumulator.add(rt_val?)?;
+        }
+        Ok(accumulator.result())
+    }
+}
+
+impl ExecScalar for Reduce {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        if self.result.is_none() {
+            self.result = Some(self.reduce()?);
+        }
+        Ok(self.result.clone().unwrap())
+    }
+}
+
+/* Fold */
+
+/// The last accumulator of a scan
+pub(super) struct Fold {
+    scan:   StreamScan,
+    result: Option<RtVal>,
+}
+
+impl Fold {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> ScalarNode {
+        ScalarNode::Fold(Fold { scan: StreamScan::new(arguments), result: None })
+    }
+
+    fn fold(&mut self) -> Result<RtVal, Error> {
+        for acc in self.scan.by_ref() {
+            acc?;
+        }
+        self.scan.accumulator()
+    }
+}
+
+impl ExecScalar for Fold {
+    fn eval(&mut self) -> Result<RtVal, Error> {
+        if self.result.is_none() {
+            self.result = Some(self.fold()?);
+        }
+        Ok(self.result.clone().unwrap())
+    }
+}
+
+/* Accumulator */
+
+/// The state of a reducer, which is fed one item at a time
+pub enum Accumulator {
+    Count(Int),
+    // Ints are summed exactly, until we encounter a float
+    Sum { total: RtVal, src_pos: ParsePos },
+    // The minimum or maximum, keeping the first one in case of a tie
+    Extremum { wanted: Ordering, best: Option<RtVal> },
+    Avg { total: Float, count: usize },
+}
+
+impl Accumulator {
+    pub fn new(op: ReduceOp, src_pos: ParsePos) -> Self {
+        match op {
+            ReduceOp::Count => Accumulator::Count(0),
+            ReduceOp::Sum { float: false } => Accumulator::Sum { total: RtVal::Int(0), src_pos },
+            ReduceOp::Sum { float: true } => Accumulator::Sum { total: RtVal::Float(0.0), src_pos },
+            ReduceOp::Min => Accumulator::Extremum { wanted: Ordering::Less, best: None },
+            ReduceOp::Max => Accumulator::Extremum { wanted: Ordering::Greater, best: None },
+            ReduceOp::Avg => Accumulator::Avg { total: 0.0, count: 0 },
+        }
+    }
+
+    pub fn add(&mut self, rt_val: RtVal) -> Result<(), Error> {
+        match self {
+            Accumulator::Count(count) =>
+                *count += 1,
+            Accumulator::Sum { total, src_pos } => {
+                *total =
+                    match (&*total, rt_val) {
+                        (RtVal::Int(x), RtVal::Int(y)) =>
+                            x.checked_add(y)
+                                .map(RtVal::Int)
+                                .ok_or(Error::IntegerOverflow(*src_pos))?,
+                        (x, y) =>
+                            RtVal::Float(x.as_float().unwrap() + y.as_float().unwrap()),
+                    };
+            }
+            Accumulator::Extremum { wanted, best } => {
+                let is_better =
+                    match best {
+                        None => true,
+                        Some(best_val) => rt_val.sort_cmp(best_val) == *wanted,
+                    };
+                if is_better {
+                    *best = Some(rt_val);
+                }
+            }
+            Accumulator::Avg { total, count } => {
+                *total += rt_val.as_float().unwrap();
+                *count += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn result(&self) -> RtVal {
+        match self {
+            Accumulator::Count(count) =>
+                RtVal::Int(*count),
+            Accumulator::Sum { total, .. } =>
+                total.clone(),
+            Accumulator::Extremum { best, .. } =>
+                best.clone().into(),
+            Accumulator::Avg { total, count } => {
+                let avg = if *count > 0 { Some(RtVal::Float(total / *count as Float)) } else { None };
+                avg.into()
+            }
+        }
+    }
+}
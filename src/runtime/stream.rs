@@ -1,8 +1,19 @@
-use std::io::{self, StdinLock};
+mod combine;
+mod group;
+mod histogram;
+mod join;
+mod quantiles;
+mod sample;
+mod sort;
+mod time;
+mod top;
+mod window;
 
-use crate::{compile::{Builtin, Expr, FunCall}, error::Error};
+use std::{cell::Cell, collections::{HashSet, VecDeque}, fs::File, io::{self, BufRead, BufReader}};
 
-use super::{scalar::{self, ExecScalar, ScalarNode}, RtVal, StreamVar};
+use crate::{compile::{Builtin, Expr, FunCall, ParsePos, Position, RangeMode}, error::Error};
+
+use super::{is_binding, scalar::{self, ExecScalar, ScalarNode}, split_binding, Int, LocalVar, RtVal, StreamVar};
 
 /// Any runtime component that behaves like a stream of runtime values
 // Note: we can't do the other way around and derive a blanket implementation
@@ -16,8 +27,43 @@ impl<T: Iterator<Item=Result<RtVal, Error>>> ExecStream for T { }
 #[allow(private_interfaces)]
 pub(super) enum StreamNode {
     Stdin(StdinState),
+    File(FileSource),
+    Concat(combine::Concat),
+    Zip(combine::Zip),
+    Interleave(combine::Interleave),
     Filter(StreamFilter),
-    Map(StreamMap)
+    Between(StreamBetween),
+    Context(StreamContext),
+    Map(StreamMap),
+    Flatten(StreamFlatten),
+    FlatMap(StreamFlatMap),
+    FilterSome(FilterSome),
+    Take(StreamTake),
+    Drop(StreamDrop),
+    Tail(StreamTail),
+    TailAfter(TailAfter),
+    TakeWhile(TakeWhile),
+    DropWhile(DropWhile),
+    Uniq(Uniq),
+    Distinct(Distinct),
+    Sort(sort::StreamSort),
+    GroupBy(group::GroupBy),
+    HashJoin(join::HashJoin),
+    Top(top::StreamTop),
+    Frequencies(top::Frequencies),
+    Scan(StreamScan),
+    Enumerate(Enumerate),
+    Lines(StreamLines),
+    Window(window::StreamWindow),
+    MovingAvg(window::MovingAvg),
+    Histogram(histogram::Histogram),
+    Bars(histogram::Bars),
+    Quantiles(quantiles::Quantiles),
+    Sample(sample::Sample),
+    Every(sample::Every),
+    Bernoulli(sample::Bernoulli),
+    BucketByTime(time::BucketByTime),
+    Bind(StreamBind),
 }
 
 // TODO consider introducing a macro for this
@@ -27,8 +73,43 @@ impl Iterator for StreamNode {
     fn next(&mut self) -> Option<Result<RtVal, Error>> {
         match self {
             Self::Stdin(s) => s.next(),
+            Self::File(f) => f.next(),
+            Self::Concat(c) => c.next(),
+            Self::Zip(z) => z.next(),
+            Self::Interleave(i) => i.next(),
             Self::Filter(f) => f.next(),
+            Self::Between(b) => b.next(),
+            Self::Context(c) => c.next(),
             Self::Map(f) => f.next(),
+            Self::Flatten(f) => f.next(),
+            Self::FlatMap(f) => f.next(),
+            Self::FilterSome(f) => f.next(),
+            Self::Take(t) => t.next(),
+            Self::Drop(d) => d.next(),
+            Self::Tail(t) => t.next(),
+            Self::TailAfter(t) => t.next(),
+            Self::TakeWhile(t) => t.next(),
+            Self::DropWhile(d) => d.next(),
+            Self::Uniq(u) => u.next(),
+            Self::Distinct(d) => d.next(),
+            Self::Sort(s) => s.next(),
+            Self::GroupBy(g) => g.next(),
+            Self::HashJoin(j) => j.next(),
+            Self::Top(t) => t.next(),
+            Self::Frequencies(f) => f.next(),
+            Self::Scan(s) => s.next(),
+            Self::Enumerate(e) => e.next(),
+            Self::Lines(l) => l.next(),
+            Self::Window(w) => w.next(),
+            Self::MovingAvg(m) => m.next(),
+            Self::Histogram(h) => h.next(),
+            Self::Bars(b) => b.next(),
+            Self::Quantiles(q) => q.next(),
+            Self::Sample(s) => s.next(),
+            Self::Every(e) => e.next(),
+            Self::Bernoulli(b) => b.next(),
+            Self::BucketByTime(b) => b.next(),
+            Self::Bind(b) => b.next(),
         }
     }
 }
@@ -38,6 +119,8 @@ impl Iterator for StreamNode {
 pub fn stream_from(expr: Expr) -> StreamNode {
     let expr_str = format!("{:?}", expr);
     match expr {
+        binding if is_binding(&binding) => StreamBind::new_node(binding),
+
         Expr::Builtin(b, _pos) => {
             match b {
                 Builtin::Stdin => StdinState::new_node(),
@@ -47,10 +130,109 @@ pub fn stream_from(expr: Expr) -> StreamNode {
 
         Expr::FunCall(fcall) => {
             match *fcall.function {
+                Expr::Builtin(Builtin::File, _pos) =>
+                    FileSource::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Concat, _pos) =>
+                    combine::Concat::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Zip, _pos) =>
+                    combine::Zip::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Interleave, _pos) =>
+                    combine::Interleave::new_node(fcall.arguments),
                 Expr::Builtin(Builtin::Filter, _pos) =>
                     StreamFilter::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Between(mode), _pos) =>
+                    StreamBetween::new_node(fcall.arguments, mode),
+                Expr::Builtin(Builtin::Context, _pos) =>
+                    StreamContext::new_node(fcall.arguments),
                 Expr::Builtin(Builtin::Map, _pos) =>
                     StreamMap::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Flatten, _pos) =>
+                    StreamFlatten::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::FlatMap { stream: true }, _pos) =>
+                    StreamFlatMap::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::FlatMap { stream: false }, pos) => {
+                    // Same as flatten (map f s)
+                    let map = FunCall::new_expr(Expr::Builtin(Builtin::Map, pos), fcall.arguments);
+                    StreamFlatten::new_node(vec![map])
+                }
+                Expr::Builtin(Builtin::SplitLines, pos) => {
+                    // Same as flat_map (split sep) s
+                    let mut args_iter = fcall.arguments.into_iter();
+                    let split = FunCall::new_expr(Expr::Builtin(Builtin::Split, pos), vec![args_iter.next().unwrap()]);
+                    let map = FunCall::new_expr(Expr::Builtin(Builtin::Map, pos), vec![split, args_iter.next().unwrap()]);
+                    StreamFlatten::new_node(vec![map])
+                }
+                Expr::Builtin(Builtin::FilterSome, _pos) =>
+                    FilterSome::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Take, _pos) =>
+                    StreamTake::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Drop, _pos) =>
+                    StreamDrop::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::TakeWhile, _pos) =>
+                    TakeWhile::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::DropWhile, _pos) =>
+                    DropWhile::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Tail, _pos) =>
+                    StreamTail::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::TailAfter, _pos) =>
+                    TailAfter::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Uniq, _pos) =>
+                    Uniq::new_node(fcall.arguments, false),
+                Expr::Builtin(Builtin::UniqCount, _pos) =>
+                    Uniq::new_node(fcall.arguments, true),
+                Expr::Builtin(Builtin::Distinct, _pos) =>
+                    Distinct::new_node(fcall.arguments, false),
+                Expr::Builtin(Builtin::DistinctBy, _pos) =>
+                    Distinct::new_node(fcall.arguments, true),
+                Expr::Builtin(Builtin::Sort, _pos) =>
+                    sort::StreamSort::new_node(fcall.arguments, false, false),
+                Expr::Builtin(Builtin::SortBy, _pos) =>
+                    sort::StreamSort::new_node(fcall.arguments, true, false),
+                Expr::Builtin(Builtin::ReverseSort, _pos) =>
+                    sort::StreamSort::new_node(fcall.arguments, false, true),
+                Expr::Builtin(Builtin::ReverseSortBy, _pos) =>
+                    sort::StreamSort::new_node(fcall.arguments, true, true),
+                Expr::Builtin(Builtin::GroupBy(order), _pos) =>
+                    group::GroupBy::new_node(fcall.arguments, order),
+                Expr::Builtin(Builtin::Top, _pos) =>
+                    top::StreamTop::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Frequencies, _pos) =>
+                    top::Frequencies::new_node(fcall.arguments, false),
+                Expr::Builtin(Builtin::Frequent, _pos) =>
+                    top::Frequencies::new_node(fcall.arguments, true),
+                // As a stream, join can only be a join between two streams
+                Expr::Builtin(Builtin::HashJoin(mode), _pos) =>
+                    join::HashJoin::new_node(fcall.arguments, mode),
+                Expr::Builtin(Builtin::Enumerate, _pos) =>
+                    Enumerate::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Lines, _pos) =>
+                    StreamLines::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Scan, _pos) =>
+                    StreamScan::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Window, _pos) =>
+                    window::StreamWindow::new_node(fcall.arguments, true),
+                Expr::Builtin(Builtin::Chunks, _pos) =>
+                    window::StreamWindow::new_node(fcall.arguments, false),
+                Expr::Builtin(Builtin::MovingAvg, _pos) =>
+                    window::MovingAvg::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Histogram, _pos) =>
+                    histogram::Histogram::new_node(fcall.arguments, false),
+                Expr::Builtin(Builtin::LogHistogram, _pos) =>
+                    histogram::Histogram::new_node(fcall.arguments, true),
+                Expr::Builtin(Builtin::Bars, _pos) =>
+                    histogram::Bars::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Quantiles, _pos) =>
+                    quantiles::Quantiles::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Sample, _pos) =>
+                    sample::Sample::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Every, _pos) =>
+                    sample::Every::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::Bernoulli, _pos) =>
+                    sample::Bernoulli::new_node(fcall.arguments),
+                Expr::Builtin(Builtin::BucketByTime, _pos) =>
+                    time::BucketByTime::new_node(fcall.arguments, false),
+                Expr::Builtin(Builtin::BucketFill, _pos) =>
+                    time::BucketByTime::new_node(fcall.arguments, true),
                 _ =>
                     panic!("Not a stream function call: {}", expr_str),
             }
@@ -64,13 +246,22 @@ pub fn stream_from(expr: Expr) -> StreamNode {
 
 /* StdinState */
 
-struct StdinState {
-    stdin_lines: io::Lines<StdinLock<'static>>,
+thread_local! {
+    // Number of the last line read by any source, as returned by lineno
+    static LINENO: Cell<Int> = const { Cell::new(0) };
+    // Stdin can be read by several streams, which share its line count
+    static STDIN_LINES: Cell<Int> = const { Cell::new(0) };
 }
 
+pub(super) fn lineno() -> Int {
+    LINENO.get()
+}
+
+struct StdinState {}
+
 impl StdinState {
     fn new_node() -> StreamNode {
-        StreamNode::Stdin(StdinState { stdin_lines: io::stdin().lines() })
+        StreamNode::Stdin(StdinState {})
     }
 }
 
@@ -80,19 +271,82 @@ impl Iterator for StdinState {
     type Item = Result<RtVal, Error>;
 
     fn next(&mut self) -> Option<Result<RtVal, Error>> {
-        self.stdin_lines
-            .next()
-            // FIXME introduce a proper error here
-            .map(|l| Ok(l.unwrap().into()))
+        // Note: stdin is only locked for a single line, as it can be read by several streams
+        let mut line_count = STDIN_LINES.get();
+        let line = read_line(&mut io::stdin().lock(), &mut line_count);
+        STDIN_LINES.set(line_count);
+        line
+    }
+}
+
+/// The next line of a reader, without its line terminator
+fn read_line<R: BufRead>(reader: &mut R, line_count: &mut Int) -> Option<RtRes> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) => None,
+        Ok(_) => {
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+            *line_count += 1;
+            LINENO.set(*line_count);
+            Some(Ok(line.into()))
+        }
+        Err(e) => Some(Err(Error::InputFailed(e.to_string()))),
+    }
+}
+
+/* FileSource */
+
+/// The lines of a file, which is opened when the stream is first pulled from
+struct FileSource {
+    path:       Box<ScalarNode>,
+    src_pos:    ParsePos,
+    reader:     Option<BufReader<File>>,
+    line_count: Int,
+}
+
+impl FileSource {
+    fn new_node(mut arguments: Vec<Expr>) -> StreamNode {
+        assert_eq!(arguments.len(), 1);
+        let path = arguments.pop().unwrap();
+        let src_pos = path.position();
+
+        let file = FileSource { path: Box::new(scalar::scalar_from(path)), src_pos, reader: None, line_count: 0 };
+        StreamNode::File(file)
+    }
+
+    fn open(&mut self) -> Result<BufReader<File>, Error> {
+        let path = self.path.eval()?.str_ref().unwrap().to_owned();
+        match File::open(&path) {
+            Ok(file) => Ok(BufReader::new(file)),
+            Err(e) => Err(Error::FileOpenFailed { path, io_err: e.to_string(), err_pos: self.src_pos }),
+        }
+    }
+}
+
+impl Iterator for FileSource {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.reader.is_none() {
+            match self.open() {
+                Ok(reader) => self.reader = Some(reader),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        read_line(self.reader.as_mut().unwrap(), &mut self.line_count)
     }
 }
 
 /* StreamFilter */
 
 struct StreamFilter {
-    filter_fn:    Box<ScalarNode>,
-    back_channel: StreamVar,
-    stream:       Box<StreamNode>,
+    filter_fn: ItemFn,
+    stream:    Box<StreamNode>,
 }
 
 impl StreamFilter {
@@ -101,19 +355,9 @@ impl StreamFilter {
         let filter_fn = args_iter.next().unwrap();
         let data_source = args_iter.next().unwrap();
 
-        // Compile the filter function as a function call
-        let (back_channel_for_me, back_channel_for_them) = StreamVar::new_pair();
-        let back_channel_read = Expr::ReadVar(back_channel_for_them);
-        let filter_fun_call = FunCall::new_expr(filter_fn, vec![back_channel_read]);
-        let rt_filter_fn = scalar::scalar_from(filter_fun_call);
-
-        // Convert the data source
-        let input_stream = stream_from(data_source);
-
         let filter = StreamFilter {
-            filter_fn:    Box::new(rt_filter_fn),
-            back_channel: back_channel_for_me,
-            stream:       Box::new(input_stream),
+            filter_fn: ItemFn::new(filter_fn, &data_source),
+            stream:    Box::new(stream_from(data_source)),
         };
 
         StreamNode::Filter(filter)
@@ -136,9 +380,7 @@ impl Iterator for StreamFilter {
                 Some(Ok(rt_val)) => {
                     let keep = rt_val.clone();
 
-                    self.back_channel.write(rt_val);
-
-                    let predicate_eval = match self.filter_fn.eval() {
+                    let predicate_eval = match self.filter_fn.apply(rt_val) {
                         Ok(v) => v,
                         Err(e) => return Some(Err(e)),
                     };
@@ -156,12 +398,175 @@ impl Iterator for StreamFilter {
     }
 }
 
+/* StreamBetween */
+
+/// Ranges of items, from an item matching the start predicate to an item matching the end one.
+/// Like awk's `/start/,/end/`, an item can both start and end an (inclusive) range.
+struct StreamBetween {
+    start:  ItemFn,
+    end:    ItemFn,
+    mode:   RangeMode,
+    inside: bool,
+    done:   bool,
+    stream: Box<StreamNode>,
+}
+
+impl StreamBetween {
+    fn new_node(arguments: Vec<Expr>, mode: RangeMode) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let start = args_iter.next().unwrap();
+        let end = args_iter.next().unwrap();
+        let data_source = args_iter.next().unwrap();
+
+        let between = StreamBetween {
+            start:  ItemFn::new(start, &data_source),
+            end:    ItemFn::new(end, &data_source),
+            mode,
+            inside: false,
+            done:   false,
+            stream: Box::new(stream_from(data_source)),
+        };
+        StreamNode::Between(between)
+    }
+
+    /// Whether the item is part of a range
+    fn select(&mut self, rt_val: &RtVal) -> Result<bool, Error> {
+        let is_start = !self.inside;
+        if is_start {
+            if !self.start.apply(rt_val.clone())?.as_bool().unwrap() {
+                return Ok(false);
+            }
+            self.inside = true;
+        }
+
+        // Exclusive ranges are the same as inclusive ones, without their first and last items
+        let inclusive = !matches!(self.mode, RangeMode::Exclusive);
+        if self.end.apply(rt_val.clone())?.as_bool().unwrap() {
+            self.inside = false;
+            self.done = matches!(self.mode, RangeMode::Once);
+            return Ok(inclusive);
+        }
+        Ok(inclusive || !is_start)
+    }
+}
+
+impl Iterator for StreamBetween {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        while !self.done {
+            match self.stream.next()? {
+                Ok(rt_val) => {
+                    match self.select(&rt_val) {
+                        Ok(true) => return Some(Ok(rt_val)),
+                        Ok(false) => continue,
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+                err@Err(_) => return Some(err),
+            }
+        }
+        None
+    }
+}
+
+/* StreamContext */
+
+/// The items matching a predicate, along with the items before and after them (like grep -B/-A).
+/// Groups of items that are not contiguous are separated by "--".
+struct StreamContext {
+    before:      CountArg,
+    after:       CountArg,
+    predicate:   ItemFn,
+    stream:      Box<StreamNode>,
+    // Items that were not output, and could be output as the context of the next match
+    previous:    VecDeque<RtVal>,
+    after_left:  usize,
+    index:       usize,
+    // Index of the last item that was output, if any
+    last_output: Option<usize>,
+    pending:     VecDeque<RtVal>,
+}
+
+impl StreamContext {
+    fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let before = CountArg::new(args_iter.next().unwrap());
+        let after = CountArg::new(args_iter.next().unwrap());
+        let predicate = args_iter.next().unwrap();
+        let data_source = args_iter.next().unwrap();
+
+        let context = StreamContext {
+            before,
+            after,
+            predicate:   ItemFn::new(predicate, &data_source),
+            stream:      Box::new(stream_from(data_source)),
+            previous:    VecDeque::new(),
+            after_left:  0,
+            index:       0,
+            last_output: None,
+            pending:     VecDeque::new(),
+        };
+        StreamNode::Context(context)
+    }
+
+    /// Handles the next item, which may or may not be output
+    fn process(&mut self, rt_val: RtVal) -> Result<(), Error> {
+        let before = self.before.get()?;
+        let after = self.after.get()?;
+        let index = self.index;
+        self.index += 1;
+
+        if self.predicate.apply(rt_val.clone())?.as_bool().unwrap() {
+            let first_index = index - self.previous.len();
+            if self.last_output.is_some_and(|last| last + 1 != first_index) {
+                self.pending.push_back(String::from("--").into());
+            }
+            self.pending.extend(self.previous.drain(..));
+            self.pending.push_back(rt_val);
+            self.last_output = Some(index);
+            self.after_left = after;
+        }
+        else if self.after_left > 0 {
+            self.pending.push_back(rt_val);
+            self.last_output = Some(index);
+            self.after_left -= 1;
+        }
+        else if before > 0 {
+            if self.previous.len() == before {
+                self.previous.pop_front();
+            }
+            self.previous.push_back(rt_val);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for StreamContext {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        loop {
+            if let Some(rt_val) = self.pending.pop_front() {
+                return Some(Ok(rt_val));
+            }
+
+            match self.stream.next()? {
+                Ok(rt_val) =>
+                    if let Err(e) = self.process(rt_val) {
+                        return Some(Err(e));
+                    },
+                err@Err(_) => return Some(err),
+            }
+        }
+    }
+}
+
 /* StreamMap */
 
 struct StreamMap {
-    map_fn:       Box<ScalarNode>,
-    back_channel: StreamVar,
-    stream:       Box<StreamNode>,
+    map_fn: ItemFn,
+    stream: Box<StreamNode>,
 }
 
 impl StreamMap {
@@ -170,19 +575,9 @@ impl StreamMap {
         let map_fn = args_iter.next().unwrap();
         let data_source = args_iter.next().unwrap();
 
-        // Compile the map function as a function call
-        let (back_channel_for_me, back_channel_for_them) = StreamVar::new_pair();
-        let back_channel_read = Expr::ReadVar(back_channel_for_them);
-        let map_fun_call = FunCall::new_expr(map_fn, vec![back_channel_read]);
-        let rt_map_fn = scalar::scalar_from(map_fun_call);
-
-        // Convert the input stream
-        let stream = stream_from(data_source);
-
         let map = StreamMap {
-            map_fn:       Box::new(rt_map_fn),
-            back_channel: back_channel_for_me,
-            stream:       Box::new(stream),
+            map_fn: ItemFn::new(map_fn, &data_source),
+            stream: Box::new(stream_from(data_source)),
         };
 
         StreamNode::Map(map)
@@ -202,14 +597,835 @@ impl Iterator for StreamMap {
             same@Some(Err(_)) => same,
 
             // We actually got a value from the stream
-            Some(Ok(rt_val)) => {
-                self.back_channel.write(rt_val);
+            Some(Ok(rt_val)) =>
+                Some(self.map_fn.apply(rt_val)),
+        }
+    }
+}
+
+/* StreamFlatten */
+
+struct StreamFlatten {
+    stream:  Box<StreamNode>,
+    current: std::vec::IntoIter<RtVal>,
+}
+
+impl StreamFlatten {
+    fn new_node(mut arguments: Vec<Expr>) -> StreamNode {
+        assert_eq!(arguments.len(), 1);
+        let data_source = arguments.pop().unwrap();
+
+        let flatten = StreamFlatten {
+            stream:  Box::new(stream_from(data_source)),
+            current: Vec::new().into_iter(),
+        };
+
+        StreamNode::Flatten(flatten)
+    }
+}
+
+impl Iterator for StreamFlatten {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<Result<RtVal, Error>> {
+        loop {
+            if let Some(elem) = self.current.next() {
+                return Some(Ok(elem));
+            }
+
+            // The current list is exhausted (or was empty), move on to the next one
+            match self.stream.next() {
+                None => return None,
+                same@Some(Err(_)) => return same,
+                Some(Ok(RtVal::List(list))) => self.current = list.into_iter(),
+                Some(Ok(_)) => panic!("StreamFlatten: not a list"),
+            }
+        }
+    }
+}
+
+/* StreamFlatMap */
+
+/// The items of the streams returned by a function, for each item in turn
+struct StreamFlatMap {
+    // Compiled anew for each item
+    function: Expr,
+    stream:   Box<StreamNode>,
+    current:  Option<Box<StreamNode>>,
+}
+
+impl StreamFlatMap {
+    fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let function = args_iter.next().unwrap();
+        let data_source = args_iter.next().unwrap();
+
+        let flat_map = StreamFlatMap { function, stream: Box::new(stream_from(data_source)), current: None };
+        StreamNode::FlatMap(flat_map)
+    }
+
+    fn stream_for(&self, item: RtVal) -> StreamNode {
+        // The item is passed through a back channel, as for ItemFn
+        let (mut back_channel_for_me, back_channel_for_them) = StreamVar::new_pair();
+        let back_channel_read = Expr::ReadVar(back_channel_for_them, self.function.position());
+        let fun_call = FunCall::new_expr(self.function.clone(), vec![back_channel_read]);
+
+        back_channel_for_me.write(item);
+        stream_from(fun_call)
+    }
+}
+
+impl Iterator for StreamFlatMap {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        loop {
+            if let Some(current) = self.current.as_mut() {
+                match current.next() {
+                    None => self.current = None,
+                    some => return some,
+                }
+            }
+
+            // The current stream is exhausted (or was empty), move on to the next item
+            match self.stream.next()? {
+                Ok(item) => self.current = Some(Box::new(self.stream_for(item))),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/* FilterSome */
+
+struct FilterSome {
+    stream: Box<StreamNode>,
+}
+
+impl FilterSome {
+    fn new_node(mut arguments: Vec<Expr>) -> StreamNode {
+        assert_eq!(arguments.len(), 1);
+        let data_source = arguments.pop().unwrap();
+
+        let filter = FilterSome { stream: Box::new(stream_from(data_source)) };
+        StreamNode::FilterSome(filter)
+    }
+}
+
+impl Iterator for FilterSome {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<Result<RtVal, Error>> {
+        loop {
+            match self.stream.next() {
+                None => return None,
+                same@Some(Err(_)) => return same,
+                Some(Ok(RtVal::Maybe(Some(v)))) => return Some(Ok(*v)),
+                // Missing value: try the next one
+                Some(Ok(RtVal::Maybe(None))) => continue,
+                Some(Ok(_)) => panic!("FilterSome: not an optional value"),
+            }
+        }
+    }
+}
+/* ItemFn */
+
+/// A function that gets applied to each item of a stream.
+/// The items are passed to the function through a back channel.
+struct ItemFn {
+    function:     Box<ScalarNode>,
+    back_channel: StreamVar,
+}
+
+impl ItemFn {
+    fn new(function: Expr, data_source: &Expr) -> Self {
+        // Compile the function as a function call
+        let (back_channel_for_me, back_channel_for_them) = StreamVar::new_pair();
+        let back_channel_read = Expr::ReadVar(back_channel_for_them, data_source.position());
+        let fun_call = FunCall::new_expr(function, vec![back_channel_read]);
+
+        ItemFn {
+            function:     Box::new(scalar::scalar_from(fun_call)),
+            back_channel: back_channel_for_me,
+        }
+    }
+
+    fn apply(&mut self, item: RtVal) -> Result<RtVal, Error> {
+        self.back_channel.write(item);
+        self.function.eval()
+    }
+}
+
+/* StepFn */
+
+/// A function of an accumulator and a stream item
+struct StepFn {
+    function:     Box<ScalarNode>,
+    acc_channel:  StreamVar,
+    item_channel: StreamVar,
+}
+
+impl StepFn {
+    fn new(function: Expr, data_source: &Expr) -> Self {
+        let (acc_for_me, acc_for_them) = StreamVar::new_pair();
+        let (item_for_me, item_for_them) = StreamVar::new_pair();
+        let arguments = vec![
+            Expr::ReadVar(acc_for_them, function.position()),
+            Expr::ReadVar(item_for_them, data_source.position()),
+        ];
+        let fun_call = FunCall::new_expr(function, arguments);
+
+        StepFn {
+            function:     Box::new(scalar::scalar_from(fun_call)),
+            acc_channel:  acc_for_me,
+            item_channel: item_for_me,
+        }
+    }
+
+    fn apply(&mut self, acc: RtVal, item: RtVal) -> Result<RtVal, Error> {
+        self.acc_channel.write(acc);
+        self.item_channel.write(item);
+        self.function.eval()
+    }
+}
+
+/* CountArg */
+
+/// A count argument, which is evaluated when the stream is first pulled from
+/// (so that it can depend on local variables)
+struct CountArg {
+    node:    Box<ScalarNode>,
+    src_pos: ParsePos,
+    value:   Option<usize>,
+}
+
+impl CountArg {
+    fn new(arg: Expr) -> Self {
+        let src_pos = arg.position();
+        let node = Box::new(scalar::scalar_from(arg));
+        CountArg { node, src_pos, value: None }
+    }
+
+    fn get(&mut self) -> Result<usize, Error> {
+        if let Some(count) = self.value {
+            return Ok(count);
+        }
+
+        let count = self.node.eval()?.as_int().unwrap();
+        let count =
+            usize::try_from(count)
+                .map_err(|_| Error::NegativeCount { count, err_pos: self.src_pos })?;
+        self.value = Some(count);
+        Ok(count)
+    }
+
+    /// The count, as the size of a window, which can't be empty
+    fn get_window_size(&mut self) -> Result<usize, Error> {
+        match self.get()? {
+            0 => Err(Error::EmptyWindow(self.src_pos)),
+            size => Ok(size),
+        }
+    }
+
+    /// The count, as a number of items to move forward, which can't be zero
+    fn get_stride(&mut self) -> Result<usize, Error> {
+        match self.get()? {
+            0 => Err(Error::ZeroStride(self.src_pos)),
+            stride => Ok(stride),
+        }
+    }
+}
+
+/* StreamTake */
+
+struct StreamTake {
+    count:  CountArg,
+    taken:  usize,
+    stream: Box<StreamNode>,
+}
+
+impl StreamTake {
+    fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let count = CountArg::new(args_iter.next().unwrap());
+        let data_source = args_iter.next().unwrap();
+
+        let take = StreamTake { count, taken: 0, stream: Box::new(stream_from(data_source)) };
+        StreamNode::Take(take)
+    }
+}
+
+impl Iterator for StreamTake {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        let count = match self.count.get() {
+            Ok(count) => count,
+            Err(e) => return Some(Err(e)),
+        };
+
+        // Don't pull anything once we're done, the data source may be endless
+        if self.taken >= count {
+            return None;
+        }
+
+        // Errors are passed along, but don't count as items
+        let next = self.stream.next();
+        if let Some(Ok(_)) = next {
+            self.taken += 1;
+        }
+        next
+    }
+}
+
+/* StreamDrop */
+
+struct StreamDrop {
+    count:   CountArg,
+    // Whether the first items were already dropped
+    dropped: bool,
+    stream:  Box<StreamNode>,
+}
+
+impl StreamDrop {
+    fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let count = CountArg::new(args_iter.next().unwrap());
+        let data_source = args_iter.next().unwrap();
+
+        let drop = StreamDrop { count, dropped: false, stream: Box::new(stream_from(data_source)) };
+        StreamNode::Drop(drop)
+    }
+
+    fn drop_first(&mut self) -> Result<(), Error> {
+        let count = self.count.get()?;
+        for _ in 0..count {
+            match self.stream.next() {
+                None => break,
+                Some(Err(e)) => return Err(e),
+                Some(Ok(_)) => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for StreamDrop {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if !self.dropped {
+            self.dropped = true;
+            if let Err(e) = self.drop_first() {
+                return Some(Err(e));
+            }
+        }
+
+        self.stream.next()
+    }
+}
+
+/* TakeWhile */
+
+/// Items up to (and without) the first one that fails a predicate
+struct TakeWhile {
+    predicate: ItemFn,
+    stream:    Box<StreamNode>,
+    done:      bool,
+}
+
+impl TakeWhile {
+    fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let predicate = args_iter.next().unwrap();
+        let data_source = args_iter.next().unwrap();
+
+        let take_while = TakeWhile {
+            predicate: ItemFn::new(predicate, &data_source),
+            stream:    Box::new(stream_from(data_source)),
+            done:      false,
+        };
+        StreamNode::TakeWhile(take_while)
+    }
+}
+
+impl Iterator for TakeWhile {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        // Don't read anything after the first failing item
+        if self.done {
+            return None;
+        }
+
+        let rt_val =
+            match self.stream.next()? {
+                Ok(rt_val) => rt_val,
+                err@Err(_) => return Some(err),
+            };
+        match self.predicate.apply(rt_val.clone()) {
+            Ok(keep) if keep.as_bool().unwrap() => Some(Ok(rt_val)),
+            Ok(_) => {
+                self.done = true;
+                None
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
 
-                match self.map_fn.eval() {
-                    Ok(v) => Some(Ok(v)),
-                    Err(e) => Some(Err(e)),
+/* DropWhile */
+
+/// Items from the first one that fails a predicate
+struct DropWhile {
+    predicate: ItemFn,
+    stream:    Box<StreamNode>,
+    dropping:  bool,
+}
+
+impl DropWhile {
+    fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let predicate = args_iter.next().unwrap();
+        let data_source = args_iter.next().unwrap();
+
+        let drop_while = DropWhile {
+            predicate: ItemFn::new(predicate, &data_source),
+            stream:    Box::new(stream_from(data_source)),
+            dropping:  true,
+        };
+        StreamNode::DropWhile(drop_while)
+    }
+}
+
+impl Iterator for DropWhile {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        loop {
+            let rt_val =
+                match self.stream.next()? {
+                    Ok(rt_val) => rt_val,
+                    err@Err(_) => return Some(err),
+                };
+            if !self.dropping {
+                return Some(Ok(rt_val));
+            }
+
+            match self.predicate.apply(rt_val.clone()) {
+                Ok(drop) if drop.as_bool().unwrap() => continue,
+                Ok(_) => {
+                    self.dropping = false;
+                    return Some(Ok(rt_val));
                 }
-            },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/* StreamTail */
+
+struct StreamTail {
+    count:  CountArg,
+    stream: Box<StreamNode>,
+    // Filled once the data source is exhausted
+    last:   Option<VecDeque<RtVal>>,
+}
+
+impl StreamTail {
+    fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let count = CountArg::new(args_iter.next().unwrap());
+        let data_source = args_iter.next().unwrap();
+
+        let tail = StreamTail { count, stream: Box::new(stream_from(data_source)), last: None };
+        StreamNode::Tail(tail)
+    }
+
+    fn fill(&mut self) -> Result<VecDeque<RtVal>, Error> {
+        let count = self.count.get()?;
+
+        // Only keep the last items, dropping the oldest ones
+        // Note: the count may be much larger than the actual number of items
+        let mut last = VecDeque::with_capacity(count.min(1024));
+        if count == 0 {
+            return Ok(last);
+        }
+        for rt_val in self.stream.as_mut() {
+            if last.len() == count {
+                last.pop_front();
+            }
+            last.push_back(rt_val?);
+        }
+        Ok(last)
+    }
+}
+
+impl Iterator for StreamTail {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.last.is_none() {
+            match self.fill() {
+                Ok(last) => self.last = Some(last),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        self.last.as_mut().unwrap().pop_front().map(Ok)
+    }
+}
+
+/* StreamLines */
+
+/// Items from the first to the last position given, starting at 1 (like `sed -n 'a,bp'`)
+struct StreamLines {
+    first:  CountArg,
+    last:   CountArg,
+    seen:   usize,
+    stream: Box<StreamNode>,
+}
+
+impl StreamLines {
+    fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let first = CountArg::new(args_iter.next().unwrap());
+        let last = CountArg::new(args_iter.next().unwrap());
+        let data_source = args_iter.next().unwrap();
+
+        let lines = StreamLines { first, last, seen: 0, stream: Box::new(stream_from(data_source)) };
+        StreamNode::Lines(lines)
+    }
+
+    fn next_line(&mut self) -> Result<Option<RtVal>, Error> {
+        let first = self.first.get()?;
+        let last = self.last.get()?;
+
+        // Don't pull anything after the last line
+        while self.seen < last {
+            let Some(rt_val) = self.stream.next() else {
+                return Ok(None);
+            };
+            self.seen += 1;
+            let rt_val = rt_val?;
+            if self.seen >= first {
+                return Ok(Some(rt_val));
+            }
         }
+        Ok(None)
     }
-}
\ No newline at end of file
+}
+
+impl Iterator for StreamLines {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        self.next_line().transpose()
+    }
+}
+
+/* TailAfter */
+
+/// All the items after the last one matching a predicate
+struct TailAfter {
+    predicate: ItemFn,
+    stream:    Box<StreamNode>,
+    // Filled once the data source is exhausted
+    after:     Option<std::vec::IntoIter<RtVal>>,
+}
+
+impl TailAfter {
+    fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let predicate = args_iter.next().unwrap();
+        let data_source = args_iter.next().unwrap();
+
+        let tail = TailAfter {
+            predicate: ItemFn::new(predicate, &data_source),
+            stream:    Box::new(stream_from(data_source)),
+            after:     None,
+        };
+        StreamNode::TailAfter(tail)
+    }
+
+    fn fill(&mut self) -> Result<Vec<RtVal>, Error> {
+        // Everything before a match is forgotten
+        let mut after = Vec::new();
+        for rt_val in self.stream.as_mut() {
+            let rt_val = rt_val?;
+            if self.predicate.apply(rt_val.clone())?.as_bool().unwrap() {
+                after.clear();
+            }
+            else {
+                after.push(rt_val);
+            }
+        }
+        Ok(after)
+    }
+}
+
+impl Iterator for TailAfter {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.after.is_none() {
+            match self.fill() {
+                Ok(after) => self.after = Some(after.into_iter()),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        self.after.as_mut().unwrap().next().map(Ok)
+    }
+}
+
+/* Uniq */
+
+/// Collapses consecutive equal items
+struct Uniq {
+    stream:   Box<StreamNode>,
+    // Whether to output the number of occurrences along with each item
+    counting: bool,
+    // The item being repeated, and the number of times it was seen so far
+    current:  Option<(RtVal, usize)>,
+}
+
+impl Uniq {
+    fn new_node(mut arguments: Vec<Expr>, counting: bool) -> StreamNode {
+        assert_eq!(arguments.len(), 1);
+        let data_source = arguments.pop().unwrap();
+
+        let uniq = Uniq { stream: Box::new(stream_from(data_source)), counting, current: None };
+        StreamNode::Uniq(uniq)
+    }
+
+    fn output(&self, rt_val: RtVal, count: usize) -> RtVal {
+        if self.counting {
+            RtVal::Tuple(vec![RtVal::Int(count as Int), rt_val])
+        }
+        else {
+            rt_val
+        }
+    }
+}
+
+impl Iterator for Uniq {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        loop {
+            match self.stream.next() {
+                None => {
+                    // Output the last run, if any
+                    let (rt_val, count) = self.current.take()?;
+                    return Some(Ok(self.output(rt_val, count)));
+                }
+                same@Some(Err(_)) => return same,
+                Some(Ok(rt_val)) => {
+                    match &mut self.current {
+                        Some((current, count)) if *current == rt_val =>
+                            *count += 1,
+                        _ => {
+                            // A new run starts: output the previous one
+                            let previous = self.current.replace((rt_val, 1));
+                            if let Some((prev_val, prev_count)) = previous {
+                                return Some(Ok(self.output(prev_val, prev_count)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/* Distinct */
+
+/// Only keeps the first occurrence of each item (or of each key)
+struct Distinct {
+    stream: Box<StreamNode>,
+    key_fn: Option<ItemFn>,
+    seen:   HashSet<RtVal>,
+}
+
+impl Distinct {
+    fn new_node(arguments: Vec<Expr>, keyed: bool) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let key_fn = if keyed { args_iter.next() } else { None };
+        let data_source = args_iter.next().unwrap();
+
+        let distinct = Distinct {
+            key_fn: key_fn.map(|key_fn| ItemFn::new(key_fn, &data_source)),
+            stream: Box::new(stream_from(data_source)),
+            seen:   HashSet::new(),
+        };
+        StreamNode::Distinct(distinct)
+    }
+
+    fn is_new(&mut self, rt_val: &RtVal) -> Result<bool, Error> {
+        let key =
+            match &mut self.key_fn {
+                Some(key_fn) => key_fn.apply(rt_val.clone())?,
+                None => rt_val.clone(),
+            };
+        Ok(self.seen.insert(key))
+    }
+}
+
+impl Iterator for Distinct {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        loop {
+            match self.stream.next() {
+                None => return None,
+                same@Some(Err(_)) => return same,
+                Some(Ok(rt_val)) => {
+                    match self.is_new(&rt_val) {
+                        Ok(true) => return Some(Ok(rt_val)),
+                        Ok(false) => continue,
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/* StreamScan */
+
+/// Outputs the accumulator after each item.
+/// Also used by `fold`, which only keeps the last accumulator.
+pub(super) struct StreamScan {
+    init:        Box<ScalarNode>,
+    step_fn:     StepFn,
+    stream:      Box<StreamNode>,
+    // Evaluated when first needed, so that it can depend on local variables
+    accumulator: Option<RtVal>,
+}
+
+impl StreamScan {
+    pub(super) fn new(arguments: Vec<Expr>) -> Self {
+        let mut args_iter = arguments.into_iter();
+        let init = args_iter.next().unwrap();
+        let step_fn = args_iter.next().unwrap();
+        let data_source = args_iter.next().unwrap();
+
+        StreamScan {
+            init:        Box::new(scalar::scalar_from(init)),
+            step_fn:     StepFn::new(step_fn, &data_source),
+            stream:      Box::new(stream_from(data_source)),
+            accumulator: None,
+        }
+    }
+
+    fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        StreamNode::Scan(Self::new(arguments))
+    }
+
+    /// The current accumulator, which is the initial value until an item is processed
+    pub(super) fn accumulator(&mut self) -> Result<RtVal, Error> {
+        if self.accumulator.is_none() {
+            self.accumulator = Some(self.init.eval()?);
+        }
+        Ok(self.accumulator.clone().unwrap())
+    }
+
+    fn step(&mut self, item: RtVal) -> Result<RtVal, Error> {
+        let acc = self.accumulator()?;
+        let new_acc = self.step_fn.apply(acc, item)?;
+        self.accumulator = Some(new_acc.clone());
+        Ok(new_acc)
+    }
+}
+
+impl Iterator for StreamScan {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        match self.stream.next()? {
+            Ok(item) => Some(self.step(item)),
+            err@Err(_) => Some(err),
+        }
+    }
+}
+
+/* Enumerate */
+
+/// Pairs of each item with its position, starting at 1
+struct Enumerate {
+    index:  Int,
+    stream: Box<StreamNode>,
+}
+
+impl Enumerate {
+    fn new_node(mut arguments: Vec<Expr>) -> StreamNode {
+        assert_eq!(arguments.len(), 1);
+        let data_source = arguments.pop().unwrap();
+
+        StreamNode::Enumerate(Enumerate { index: 0, stream: Box::new(stream_from(data_source)) })
+    }
+}
+
+impl Iterator for Enumerate {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        match self.stream.next()? {
+            Ok(rt_val) => {
+                self.index += 1;
+                Some(Ok(RtVal::Tuple(vec![RtVal::Int(self.index), rt_val])))
+            }
+            err@Err(_) => Some(err),
+        }
+    }
+}
+
+/* StreamBind */
+
+/// A stream that depends on local variables, which are set before pulling the first item
+struct StreamBind {
+    // Emptied once the variables are set
+    bindings: Vec<(LocalVar, ScalarNode)>,
+    stream:   Box<StreamNode>,
+}
+
+impl StreamBind {
+    fn new_node(binding_expr: Expr) -> StreamNode {
+        let (bindings, body) = split_binding(binding_expr);
+
+        let bindings =
+            bindings.into_iter()
+                .map(|(var, value)| (var, scalar::scalar_from(value)))
+                .collect();
+        let stream = Box::new(stream_from(body));
+
+        StreamNode::Bind(StreamBind { bindings, stream })
+    }
+
+    fn bind(&mut self) -> Result<(), Error> {
+        let mut values = Vec::with_capacity(self.bindings.len());
+        for (_var, value) in self.bindings.iter_mut() {
+            values.push(value.eval()?);
+        }
+        for ((var, _value), rt_val) in self.bindings.drain(..).zip(values) {
+            var.set(rt_val);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for StreamBind {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if !self.bindings.is_empty() {
+            if let Err(e) = self.bind() {
+                return Some(Err(e));
+            }
+        }
+
+        self.stream.next()
+    }
+}
@@ -0,0 +1,104 @@
+use crate::compile::Expr;
+
+use super::{stream_from, RtRes, StreamNode};
+use super::super::RtVal;
+
+fn two_streams(arguments: Vec<Expr>) -> [Box<StreamNode>; 2] {
+    let mut args_iter = arguments.into_iter().map(|arg| Box::new(stream_from(arg)));
+    [args_iter.next().unwrap(), args_iter.next().unwrap()]
+}
+
+/* Concat */
+
+/// All the items of the first stream, then all the items of the second one
+pub(super) struct Concat {
+    streams: [Box<StreamNode>; 2],
+    current: usize,
+}
+
+impl Concat {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        StreamNode::Concat(Concat { streams: two_streams(arguments), current: 0 })
+    }
+}
+
+impl Iterator for Concat {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        loop {
+            let next = self.streams.get_mut(self.current)?.next();
+            if next.is_some() {
+                return next;
+            }
+            self.current += 1;
+        }
+    }
+}
+
+/* Zip */
+
+/// Pairs of items, until either stream ends
+pub(super) struct Zip {
+    streams: [Box<StreamNode>; 2],
+}
+
+impl Zip {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        StreamNode::Zip(Zip { streams: two_streams(arguments) })
+    }
+}
+
+impl Iterator for Zip {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        let [left, right] = &mut self.streams;
+        // An error on the left is reported even if the right stream has ended
+        let left_val = match left.next()? {
+            Ok(left_val) => left_val,
+            Err(e) => return Some(Err(e)),
+        };
+        let pair =
+            match right.next()? {
+                Ok(right_val) => RtVal::Tuple(vec![left_val, right_val]),
+                Err(e) => return Some(Err(e)),
+            };
+        Some(Ok(pair))
+    }
+}
+
+/* Interleave */
+
+/// Items taken alternately from each stream, until both of them end
+pub(super) struct Interleave {
+    streams: [Box<StreamNode>; 2],
+    turn:    usize,
+    ended:   [bool; 2],
+}
+
+impl Interleave {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        StreamNode::Interleave(Interleave { streams: two_streams(arguments), turn: 0, ended: [false; 2] })
+    }
+}
+
+impl Iterator for Interleave {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        while !self.ended.iter().all(|&ended| ended) {
+            let current = self.turn;
+            self.turn = 1 - self.turn;
+            if self.ended[current] {
+                continue;
+            }
+
+            match self.streams[current].next() {
+                None => self.ended[current] = true,
+                some => return some,
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use crate::{compile::{Builtin, Expr, GroupOrder, ParsePos, Position, ReduceOp}, error::Error};
+
+use super::{stream_from, ItemFn, RtRes, StreamNode};
+use super::super::{scalar::reduce::Accumulator, RtVal};
+
+/// Aggregates the items of each group as they come, and outputs (key, value) pairs
+/// once the data source is exhausted, in the given order
+pub(super) struct GroupBy {
+    key_fn:  ItemFn,
+    order:   GroupOrder,
+    op:      ReduceOp,
+    agg_pos: ParsePos,
+    // Applied to each item before it is given to the reducer
+    steps:   Vec<GroupStep>,
+    stream:  Box<StreamNode>,
+    // Available once the data source is exhausted
+    output:  Option<std::vec::IntoIter<RtVal>>,
+}
+
+pub(super) enum GroupStep {
+    Map(ItemFn),
+    Filter(ItemFn),
+    FilterSome,
+}
+
+impl GroupBy {
+    pub(super) fn new_node(arguments: Vec<Expr>, order: GroupOrder) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let key_fn = args_iter.next().unwrap();
+        let agg = args_iter.next().unwrap();
+        let data_source = args_iter.next().unwrap();
+
+        let agg_pos = agg.position();
+        let (op, steps) = aggregation_steps(agg, &data_source);
+        let group_by = GroupBy {
+            key_fn: ItemFn::new(key_fn, &data_source),
+            order,
+            op,
+            agg_pos,
+            steps,
+            stream: Box::new(stream_from(data_source)),
+            output: None,
+        };
+        StreamNode::GroupBy(group_by)
+    }
+
+    fn group(&mut self) -> Result<Vec<RtVal>, Error> {
+        let mut indices = HashMap::new();
+        let mut groups: Vec<(RtVal, Accumulator)> = Vec::new();
+
+        for rt_val in self.stream.as_mut() {
+            let rt_val = rt_val?;
+            let key = self.key_fn.apply(rt_val.clone())?;
+            let idx =
+                match indices.get(&key) {
+                    Some(&idx) => idx,
+                    None => {
+                        // Groups exist even if all their items get filtered out
+                        indices.insert(key.clone(), groups.len());
+                        groups.push((key, Accumulator::new(self.op, self.agg_pos)));
+                        groups.len() - 1
+                    }
+                };
+
+            if let Some(value) = apply_steps(&mut self.steps, rt_val)? {
+                groups[idx].1.add(value)?;
+            }
+        }
+
+        let mut output: Vec<(RtVal, RtVal)> =
+            groups.into_iter()
+                .map(|(key, accumulator)| (key, accumulator.result()))
+                .collect();
+        // Stable sorts, so that ties stay in first-seen order
+        match self.order {
+            GroupOrder::FirstSeen => (),
+            GroupOrder::Key => output.sort_by(|(l, _), (r, _)| l.sort_cmp(r)),
+            GroupOrder::Value => output.sort_by(|(_, l), (_, r)| l.sort_cmp(r)),
+        }
+        Ok(output.into_iter().map(|(key, value)| RtVal::Tuple(vec![key, value])).collect())
+    }
+}
+
+impl Iterator for GroupBy {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.output.is_none() {
+            match self.group() {
+                Ok(output) => self.output = Some(output.into_iter()),
+                Err(e) => {
+                    // Don't try again after an error
+                    self.output = Some(Vec::new().into_iter());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.output.as_mut().unwrap().next().map(Ok)
+    }
+}
+
+/// Splits an aggregation into its reducer and the steps applied to each item,
+/// in application order.
+/// Typechecking made sure that the aggregation has one of the supported shapes.
+pub(super) fn aggregation_steps(agg: Expr, data_source: &Expr) -> (ReduceOp, Vec<GroupStep>) {
+    let body =
+        match agg {
+            Expr::Builtin(Builtin::Reduce(op), _pos) => return (op, Vec::new()),
+            Expr::Lambda(lambda) => *lambda.body,
+            _ => panic!("Not an aggregation: {:?}", agg),
+        };
+
+    let (op, mut pipeline) =
+        match body {
+            Expr::FunCall(mut fcall) =>
+                match *fcall.function {
+                    Expr::Builtin(Builtin::Reduce(op), _pos) => (op, fcall.arguments.pop().unwrap()),
+                    _ => panic!("Not a reducer: {:?}", fcall.function),
+                },
+            _ => panic!("Not an aggregation: {:?}", body),
+        };
+
+    // The pipeline is nested, the last step being the outermost call
+    let mut steps = Vec::new();
+    while let Expr::FunCall(fcall) = pipeline {
+        let mut args = fcall.arguments;
+        let source = args.pop().unwrap();
+        steps.push(
+            match *fcall.function {
+                Expr::Builtin(Builtin::Map, _pos) =>
+                    GroupStep::Map(ItemFn::new(args.pop().unwrap(), data_source)),
+                Expr::Builtin(Builtin::Filter, _pos) =>
+                    GroupStep::Filter(ItemFn::new(args.pop().unwrap(), data_source)),
+                Expr::Builtin(Builtin::FilterSome, _pos) =>
+                    GroupStep::FilterSome,
+                other => panic!("Not a group step: {:?}", other),
+            });
+        pipeline = source;
+    }
+    steps.reverse();
+
+    (op, steps)
+}
+
+/// The value given to the reducer for an item, unless the item is filtered out
+pub(super) fn apply_steps(steps: &mut [GroupStep], mut rt_val: RtVal) -> Result<Option<RtVal>, Error> {
+    for step in steps {
+        match step {
+            GroupStep::Map(map_fn) =>
+                rt_val = map_fn.apply(rt_val)?,
+            GroupStep::Filter(filter_fn) =>
+                if !filter_fn.apply(rt_val.clone())?.as_bool().unwrap() {
+                    return Ok(None);
+                },
+            GroupStep::FilterSome =>
+                match rt_val {
+                    RtVal::Maybe(Some(inner)) => rt_val = *inner,
+                    RtVal::Maybe(None) => return Ok(None),
+                    _ => panic!("FilterSome: not an optional value"),
+                },
+        }
+    }
+    Ok(Some(rt_val))
+}
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+
+use crate::{compile::{Expr, ParsePos, Position}, error::Error};
+
+use super::{stream_from, RtRes, StreamNode};
+use super::super::{scalar::{self, ExecScalar, ScalarNode}, Float, Int, RtVal};
+
+// Guards against a tiny width over a large range, which would print forever
+pub(super) const MAX_BUCKETS: i64 = 1_000_000;
+
+// Number of characters of the longest bar
+const BAR_WIDTH: Int = 40;
+
+/* Histogram */
+
+/// (count, lower bound, upper bound) tuples, where buckets include their lower bound.
+/// Empty buckets between the smallest and largest values are included too.
+pub(super) struct Histogram {
+    layout:  Box<ScalarNode>,
+    log:     bool,
+    src_pos: ParsePos,
+    stream:  Box<StreamNode>,
+    // Available once the data source is exhausted
+    output:  Option<std::vec::IntoIter<RtVal>>,
+}
+
+enum Layout {
+    Width(Float),
+    Log(Float),
+    // Values below the first boundary or above the last one get their own bucket
+    Bounds(Vec<Float>),
+}
+
+impl Layout {
+    fn new(layout: RtVal, log: bool, src_pos: ParsePos) -> Result<Self, Error> {
+        let invalid = |reason| Error::InvalidBuckets { reason, err_pos: src_pos };
+
+        if let Some(bounds) = layout.as_list() {
+            let bounds: Vec<Float> = bounds.iter().map(|b| b.as_float().unwrap()).collect();
+            if bounds.windows(2).any(|pair| pair[0] >= pair[1]) || bounds.iter().any(|b| b.is_nan()) {
+                return Err(invalid("the boundaries must be in increasing order"));
+            }
+            return Ok(Layout::Bounds(bounds));
+        }
+
+        let value = layout.as_float().unwrap();
+        if log {
+            if value.is_nan() || value <= 1.0 || value.is_infinite() {
+                return Err(invalid("the logarithm base must be greater than 1"));
+            }
+            Ok(Layout::Log(value))
+        }
+        else {
+            if value.is_nan() || value <= 0.0 || value.is_infinite() {
+                return Err(invalid("the bucket width must be positive"));
+            }
+            Ok(Layout::Width(value))
+        }
+    }
+
+    /// None for the values that don't fit any bucket
+    fn bucket(&self, x: Float) -> Option<i64> {
+        match self {
+            Layout::Bounds(bounds) if !x.is_nan() =>
+                Some(bounds.partition_point(|b| *b <= x) as i64),
+            Layout::Width(width) if x.is_finite() =>
+                Some((x / width).floor() as i64),
+            Layout::Log(base) if x.is_finite() && x > 0.0 => {
+                // Logarithms are not exact, so check the result against powers of the base
+                let mut k = (x.ln() / base.ln()).floor() as i32;
+                if base.powi(k) > x {
+                    k -= 1;
+                }
+                else if base.powi(k + 1) <= x {
+                    k += 1;
+                }
+                Some(k as i64)
+            }
+            _ => None,
+        }
+    }
+
+    fn range(&self, bucket: i64) -> (Float, Float) {
+        match self {
+            Layout::Bounds(bounds) => {
+                let idx = bucket as usize;
+                let lower = if idx == 0 { Float::NEG_INFINITY } else { bounds[idx - 1] };
+                let upper = bounds.get(idx).copied().unwrap_or(Float::INFINITY);
+                (lower, upper)
+            }
+            Layout::Width(width) => {
+                // Dividing by the inverse avoids printing 0.30000000000000004 for a width of 0.1
+                let inverse = 1.0 / width;
+                if *width < 1.0 && inverse.fract() == 0.0 {
+                    (bucket as Float / inverse, (bucket + 1) as Float / inverse)
+                }
+                else {
+                    (bucket as Float * width, (bucket + 1) as Float * width)
+                }
+            }
+            Layout::Log(base) =>
+                (base.powi(bucket as i32), base.powi(bucket as i32 + 1)),
+        }
+    }
+
+    /// The buckets to display, given the non-empty ones
+    fn displayed(&self, counts: &BTreeMap<i64, Int>) -> (i64, i64) {
+        match self {
+            Layout::Bounds(bounds) => {
+                // Inner buckets are always displayed, but not empty outer ones
+                let last = bounds.len() as i64;
+                let first = if counts.contains_key(&0) { 0 } else { 1 };
+                let last = if counts.contains_key(&last) { last } else { last - 1 };
+                (first, last)
+            }
+            _ => {
+                let first = counts.keys().next().copied().unwrap_or(0);
+                let last = counts.keys().next_back().copied().unwrap_or(-1);
+                (first, last)
+            }
+        }
+    }
+}
+
+impl Histogram {
+    pub(super) fn new_node(arguments: Vec<Expr>, log: bool) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let layout_arg = args_iter.next().unwrap();
+        let src_pos = layout_arg.position();
+        let data_source = args_iter.next().unwrap();
+
+        let histogram = Histogram {
+            layout: Box::new(scalar::scalar_from(layout_arg)),
+            log,
+            src_pos,
+            stream: Box::new(stream_from(data_source)),
+            output: None,
+        };
+        StreamNode::Histogram(histogram)
+    }
+
+    fn histogram(&mut self) -> Result<Vec<RtVal>, Error> {
+        let layout = Layout::new(self.layout.eval()?, self.log, self.src_pos)?;
+
+        let mut counts: BTreeMap<i64, Int> = BTreeMap::new();
+        let mut non_positive = 0;
+        for rt_val in self.stream.as_mut() {
+            let x = rt_val?.as_float().unwrap();
+            match layout.bucket(x) {
+                Some(bucket) => *counts.entry(bucket).or_default() += 1,
+                // Logarithmic buckets can't hold zero and negative values, they get their own
+                None if self.log && x <= 0.0 => non_positive += 1,
+                None => (),
+            }
+        }
+
+        let (first, last) = layout.displayed(&counts);
+        if last.saturating_sub(first) >= MAX_BUCKETS {
+            return Err(Error::InvalidBuckets { reason: "too many buckets for the range of values", err_pos: self.src_pos });
+        }
+
+        let mut buckets = Vec::new();
+        if non_positive > 0 {
+            buckets.push(bucket_tuple(non_positive, Float::NEG_INFINITY, 0.0));
+        }
+        for bucket in first..=last {
+            let (lower, upper) = layout.range(bucket);
+            let count = counts.get(&bucket).copied().unwrap_or(0);
+            buckets.push(bucket_tuple(count, lower, upper));
+        }
+        Ok(buckets)
+    }
+}
+
+fn bucket_tuple(count: Int, lower: Float, upper: Float) -> RtVal {
+    RtVal::Tuple(vec![RtVal::Int(count), RtVal::Float(lower), RtVal::Float(upper)])
+}
+
+impl Iterator for Histogram {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.output.is_none() {
+            match self.histogram() {
+                Ok(buckets) => self.output = Some(buckets.into_iter()),
+                Err(e) => {
+                    self.output = Some(Vec::new().into_iter());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.output.as_mut().unwrap().next().map(Ok)
+    }
+}
+
+/* Bars */
+
+/// Appends a bar to tuples that start with a count, the largest count getting the longest bar
+pub(super) struct Bars {
+    stream: Box<StreamNode>,
+    // Available once the data source is exhausted
+    output: Option<std::vec::IntoIter<RtVal>>,
+}
+
+impl Bars {
+    pub(super) fn new_node(mut arguments: Vec<Expr>) -> StreamNode {
+        assert_eq!(arguments.len(), 1);
+        let data_source = arguments.pop().unwrap();
+
+        let bars = Bars { stream: Box::new(stream_from(data_source)), output: None };
+        StreamNode::Bars(bars)
+    }
+
+    fn bars(&mut self) -> Result<Vec<RtVal>, Error> {
+        let items: Vec<RtVal> = self.stream.as_mut().collect::<Result<_, _>>()?;
+        let count_of = |item: &RtVal| match item {
+            RtVal::Tuple(elems) => elems[0].as_int().unwrap(),
+            _ => unreachable!("bars over a non-tuple item"),
+        };
+        let max_count = items.iter().map(count_of).max().unwrap_or(0);
+
+        let with_bars =
+            items.into_iter()
+                .map(|item| {
+                    let count = count_of(&item);
+                    // Non-empty buckets always get something to see
+                    let len =
+                        if count <= 0 { 0 }
+                        else { ((count as Float * BAR_WIDTH as Float / max_count as Float).round() as usize).max(1) };
+                    let RtVal::Tuple(mut elems) = item else { unreachable!() };
+                    elems.push(RtVal::String("#".repeat(len)));
+                    RtVal::Tuple(elems)
+                })
+                .collect();
+        Ok(with_bars)
+    }
+}
+
+impl Iterator for Bars {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.output.is_none() {
+            match self.bars() {
+                Ok(bars) => self.output = Some(bars.into_iter()),
+                Err(e) => {
+                    self.output = Some(Vec::new().into_iter());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.output.as_mut().unwrap().next().map(Ok)
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{compile::{Expr, JoinMode}, error::Error};
+
+use super::{stream_from, ItemFn, RtRes, StreamNode};
+use super::super::RtVal;
+
+/// Joins the items of the first stream with the items of the second one that have the same key.
+/// The second stream (e.g. a lookup file) is read entirely into a hash table,
+/// while the first one is only read as needed.
+pub(super) struct HashJoin {
+    left_key:  ItemFn,
+    right_key: ItemFn,
+    mode:      JoinMode,
+    left:      Box<StreamNode>,
+    right:     Box<StreamNode>,
+    // Filled when the stream is first pulled from
+    table:     Option<HashMap<RtVal, Vec<RtVal>>>,
+    pending:   VecDeque<RtVal>,
+}
+
+impl HashJoin {
+    pub(super) fn new_node(arguments: Vec<Expr>, mode: JoinMode) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let left_key = args_iter.next().unwrap();
+        let right_key = args_iter.next().unwrap();
+        let left = args_iter.next().unwrap();
+        let right = args_iter.next().unwrap();
+
+        let join = HashJoin {
+            left_key:  ItemFn::new(left_key, &left),
+            right_key: ItemFn::new(right_key, &right),
+            mode,
+            left:      Box::new(stream_from(left)),
+            right:     Box::new(stream_from(right)),
+            table:     None,
+            pending:   VecDeque::new(),
+        };
+        StreamNode::HashJoin(join)
+    }
+
+    fn build_table(&mut self) -> Result<HashMap<RtVal, Vec<RtVal>>, Error> {
+        let mut table: HashMap<RtVal, Vec<RtVal>> = HashMap::new();
+        for rt_val in self.right.as_mut() {
+            let rt_val = rt_val?;
+            let key = self.right_key.apply(rt_val.clone())?;
+            table.entry(key).or_default().push(rt_val);
+        }
+        Ok(table)
+    }
+
+    /// Adds the output for an item of the first stream to the pending items
+    fn probe(&mut self, rt_val: RtVal) -> Result<(), Error> {
+        if self.table.is_none() {
+            self.table = Some(self.build_table()?);
+        }
+
+        let key = self.left_key.apply(rt_val.clone())?;
+        let matches = self.table.as_ref().unwrap().get(&key);
+        match self.mode {
+            JoinMode::Inner =>
+                for right_val in matches.into_iter().flatten() {
+                    self.pending.push_back(RtVal::Tuple(vec![rt_val.clone(), right_val.clone()]));
+                },
+            JoinMode::Left =>
+                match matches {
+                    Some(matches) =>
+                        for right_val in matches {
+                            let right_val = Some(right_val.clone()).into();
+                            self.pending.push_back(RtVal::Tuple(vec![rt_val.clone(), right_val]));
+                        },
+                    None =>
+                        self.pending.push_back(RtVal::Tuple(vec![rt_val, None.into()])),
+                },
+            JoinMode::Anti =>
+                if matches.is_none() {
+                    self.pending.push_back(rt_val);
+                },
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for HashJoin {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        loop {
+            if let Some(rt_val) = self.pending.pop_front() {
+                return Some(Ok(rt_val));
+            }
+
+            match self.left.next()? {
+                Ok(rt_val) =>
+                    if let Err(e) = self.probe(rt_val) {
+                        return Some(Err(e));
+                    },
+                err@Err(_) => return Some(err),
+            }
+        }
+    }
+}
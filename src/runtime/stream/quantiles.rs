@@ -0,0 +1,77 @@
+use crate::{compile::{Expr, ParsePos, Position}, error::Error};
+
+use super::{stream_from, RtRes, StreamNode};
+use super::super::{scalar::{self, percentile, ExecScalar, ScalarNode}, RtVal};
+
+/* Quantiles */
+
+/// (percentile, value, error bound) tuples, in the order of the requested percentiles.
+/// The error bound is 0 unless the values didn't fit in memory.
+pub(super) struct Quantiles {
+    percentiles: Box<ScalarNode>,
+    src_pos:     ParsePos,
+    stream:      Box<StreamNode>,
+    // Available once the data source is exhausted
+    output:      Option<std::vec::IntoIter<RtVal>>,
+}
+
+impl Quantiles {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let percentiles_arg = args_iter.next().unwrap();
+        let src_pos = percentiles_arg.position();
+        let data_source = args_iter.next().unwrap();
+
+        let quantiles = Quantiles {
+            percentiles: Box::new(scalar::scalar_from(percentiles_arg)),
+            src_pos,
+            stream: Box::new(stream_from(data_source)),
+            output: None,
+        };
+        StreamNode::Quantiles(quantiles)
+    }
+
+    fn quantiles(&mut self) -> Result<Vec<RtVal>, Error> {
+        // Check the percentiles before reading the whole input
+        let percentiles: Vec<_> =
+            self.percentiles.eval()?
+                .as_list().unwrap()
+                .iter()
+                .map(|p| p.as_float().unwrap())
+                .collect();
+        for p in &percentiles {
+            percentile::check_percentile(*p, self.src_pos)?;
+        }
+
+        let mut estimator = percentile::Quantiles::new();
+        for rt_val in self.stream.as_mut() {
+            estimator.add(rt_val?.as_float().unwrap());
+        }
+
+        let rows =
+            percentiles.into_iter()
+                .filter_map(|p| {
+                    let (value, error) = estimator.quantile(p)?;
+                    Some(RtVal::Tuple(vec![RtVal::Float(p), RtVal::Float(value), RtVal::Float(error)]))
+                })
+                .collect();
+        Ok(rows)
+    }
+}
+
+impl Iterator for Quantiles {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.output.is_none() {
+            match self.quantiles() {
+                Ok(rows) => self.output = Some(rows.into_iter()),
+                Err(e) => {
+                    self.output = Some(Vec::new().into_iter());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.output.as_mut().unwrap().next().map(Ok)
+    }
+}
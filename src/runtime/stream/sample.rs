@@ -0,0 +1,213 @@
+use std::{cell::Cell, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::{compile::{Expr, ParsePos, Position}, error::Error};
+
+use super::{stream_from, CountArg, RtRes, StreamNode};
+use super::super::{scalar::{self, ExecScalar, ScalarNode}, settings, Float, RtVal};
+
+thread_local! {
+    // Each sampler gets its own random sequence, even with a fixed seed
+    static SAMPLERS: Cell<u64> = const { Cell::new(0) };
+}
+
+/* Rng */
+
+/// SplitMix64, which is plenty for sampling (and needs no dependency)
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new() -> Self {
+        let seed =
+            settings().seed.unwrap_or_else(|| {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+                now.as_nanos() as u64 ^ ((std::process::id() as u64) << 32)
+            });
+        let sampler = SAMPLERS.with(|samplers| samplers.replace(samplers.get() + 1));
+
+        let mut rng = Rng { state: seed };
+        rng.state ^= Rng { state: sampler }.next_u64();
+        rng
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    fn next_float(&mut self) -> Float {
+        (self.next_u64() >> 11) as Float / (1u64 << 53) as Float
+    }
+
+    /// Uniform in [0, bound)
+    fn below(&mut self, bound: usize) -> usize {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as usize
+    }
+}
+
+/* Sample */
+
+/// A uniform sample of n items over the whole stream (reservoir sampling),
+/// in the order of the stream
+pub(super) struct Sample {
+    count:  CountArg,
+    rng:    Rng,
+    stream: Box<StreamNode>,
+    // Available once the data source is exhausted
+    output: Option<std::vec::IntoIter<RtVal>>,
+}
+
+impl Sample {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let count = CountArg::new(args_iter.next().unwrap());
+        let data_source = args_iter.next().unwrap();
+
+        let sample = Sample { count, rng: Rng::new(), stream: Box::new(stream_from(data_source)), output: None };
+        StreamNode::Sample(sample)
+    }
+
+    fn sample(&mut self) -> Result<Vec<RtVal>, Error> {
+        let count = self.count.get()?;
+
+        // Every item replaces a random one of the reservoir, with a decreasing probability
+        let mut reservoir: Vec<(usize, RtVal)> = Vec::with_capacity(count.min(1024));
+        for (seq, rt_val) in self.stream.as_mut().enumerate() {
+            let item = rt_val?;
+            if reservoir.len() < count {
+                reservoir.push((seq, item));
+            }
+            else {
+                let idx = self.rng.below(seq + 1);
+                if idx < count {
+                    reservoir[idx] = (seq, item);
+                }
+            }
+        }
+
+        reservoir.sort_by_key(|(seq, _item)| *seq);
+        Ok(reservoir.into_iter().map(|(_seq, item)| item).collect())
+    }
+}
+
+impl Iterator for Sample {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.output.is_none() {
+            match self.sample() {
+                Ok(sample) => self.output = Some(sample.into_iter()),
+                Err(e) => {
+                    self.output = Some(Vec::new().into_iter());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.output.as_mut().unwrap().next().map(Ok)
+    }
+}
+
+/* Every */
+
+/// Every nth item, starting with the nth one
+pub(super) struct Every {
+    stride: CountArg,
+    stream: Box<StreamNode>,
+}
+
+impl Every {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let stride = CountArg::new(args_iter.next().unwrap());
+        let data_source = args_iter.next().unwrap();
+
+        StreamNode::Every(Every { stride, stream: Box::new(stream_from(data_source)) })
+    }
+}
+
+impl Iterator for Every {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        let stride = match self.stride.get_stride() {
+            Ok(stride) => stride,
+            Err(e) => return Some(Err(e)),
+        };
+
+        // Skip the items in between, but stop at the end of the stream or on an error
+        for _ in 1..stride {
+            match self.stream.next()? {
+                Ok(_) => (),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.stream.next()
+    }
+}
+
+/* Bernoulli */
+
+/// Keeps every item with the same probability, independently of the others
+pub(super) struct Bernoulli {
+    probability: Box<ScalarNode>,
+    src_pos:     ParsePos,
+    // Checked on the first item
+    checked:     Option<Float>,
+    rng:         Rng,
+    stream:      Box<StreamNode>,
+}
+
+impl Bernoulli {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let probability_arg = args_iter.next().unwrap();
+        let src_pos = probability_arg.position();
+        let data_source = args_iter.next().unwrap();
+
+        let bernoulli = Bernoulli {
+            probability: Box::new(scalar::scalar_from(probability_arg)),
+            src_pos,
+            checked: None,
+            rng: Rng::new(),
+            stream: Box::new(stream_from(data_source)),
+        };
+        StreamNode::Bernoulli(bernoulli)
+    }
+
+    fn probability(&mut self) -> Result<Float, Error> {
+        if let Some(probability) = self.checked {
+            return Ok(probability);
+        }
+
+        let probability = self.probability.eval()?.as_float().unwrap();
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(Error::InvalidProbability { probability, err_pos: self.src_pos });
+        }
+        self.checked = Some(probability);
+        Ok(probability)
+    }
+}
+
+impl Iterator for Bernoulli {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        let probability = match self.probability() {
+            Ok(probability) => probability,
+            Err(e) => return Some(Err(e)),
+        };
+
+        loop {
+            match self.stream.next()? {
+                Ok(item) if self.rng.next_float() < probability => return Some(Ok(item)),
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    cmp::Ordering,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
+
+use crate::{compile::Expr, error::Error};
+
+use super::{stream_from, ItemFn, RtRes, StreamNode};
+use super::super::{settings, RtVal};
+
+/// Maximum number of run files merged at once, which bounds the number of open files
+const MAX_FAN_IN: usize = 64;
+
+/// Sorts a stream, using temporary files if it doesn't fit in the memory budget.
+/// The sort is stable, including in reverse order.
+pub(super) struct StreamSort {
+    key_fn:     Option<ItemFn>,
+    descending: bool,
+    stream:     Box<StreamNode>,
+    // Available once the data source is exhausted
+    merge:      Option<Merge>,
+}
+
+impl StreamSort {
+    pub(super) fn new_node(arguments: Vec<Expr>, keyed: bool, descending: bool) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let key_fn = if keyed { args_iter.next() } else { None };
+        let data_source = args_iter.next().unwrap();
+
+        let sort = StreamSort {
+            key_fn: key_fn.map(|key_fn| ItemFn::new(key_fn, &data_source)),
+            descending,
+            stream: Box::new(stream_from(data_source)),
+            merge:  None,
+        };
+        StreamNode::Sort(sort)
+    }
+
+    fn sort_runs(&mut self) -> Result<Merge, Error> {
+        let mem_budget = settings().sort_mem;
+        let descending = self.descending;
+        let sort_run = |entries: &mut Vec<SortEntry>| entries.sort_by(|l, r| l.cmp(r, descending));
+
+        let mut file_runs = Vec::new();
+        let mut entries = Vec::new();
+        let mut mem_used = 0;
+
+        for rt_val in self.stream.as_mut() {
+            let rt_val = rt_val?;
+            let entry =
+                match &mut self.key_fn {
+                    Some(key_fn) => SortEntry { key: key_fn.apply(rt_val.clone())?, item: Some(rt_val) },
+                    None => SortEntry { key: rt_val, item: None },
+                };
+
+            mem_used += entry.approx_size();
+            entries.push(entry);
+
+            if mem_used > mem_budget {
+                sort_run(&mut entries);
+                file_runs.push(RunFile::write(entries.drain(..).map(Ok))?);
+                mem_used = 0;
+
+                if file_runs.len() == MAX_FAN_IN {
+                    // Merge the existing runs into a single one, which holds the first items
+                    let runs = file_runs.drain(..).map(|run_file| Ok(Run::File(run_file.reader()?)));
+                    let merge = Merge::new(runs.collect::<Result<_, Error>>()?, descending)?;
+                    file_runs.push(RunFile::write(merge)?);
+                }
+            }
+        }
+
+        // The last run stays in memory
+        sort_run(&mut entries);
+
+        // Note: the order of the runs matters for stability, as the first runs hold the first items
+        let mut runs = Vec::with_capacity(file_runs.len() + 1);
+        for run_file in file_runs {
+            runs.push(Run::File(run_file.reader()?));
+        }
+        runs.push(Run::Memory(entries.into_iter()));
+
+        Merge::new(runs, descending)
+    }
+}
+
+impl Iterator for StreamSort {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.merge.is_none() {
+            match self.sort_runs() {
+                Ok(merge) => self.merge = Some(merge),
+                Err(e) => {
+                    // Don't try again after an error
+                    self.merge = Some(Merge::empty(self.descending));
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        let entry = self.merge.as_mut().unwrap().next()?;
+        Some(entry.map(SortEntry::into_item))
+    }
+}
+
+/* Sort entries */
+
+struct SortEntry {
+    key:  RtVal,
+    // None when the item is its own key
+    item: Option<RtVal>,
+}
+
+impl SortEntry {
+    fn cmp(&self, other: &SortEntry, descending: bool) -> Ordering {
+        let ordering = self.key.sort_cmp(&other.key);
+        if descending { ordering.reverse() } else { ordering }
+    }
+
+    fn approx_size(&self) -> usize {
+        self.key.approx_size() + self.item.as_ref().map(RtVal::approx_size).unwrap_or(0)
+    }
+
+    fn into_item(self) -> RtVal {
+        self.item.unwrap_or(self.key)
+    }
+}
+
+/* K-way merge */
+
+/// Merges sorted runs, preferring the first runs in case of a tie
+struct Merge {
+    runs:       Vec<Run>,
+    // The next entry of each run, if it's not exhausted
+    heads:      Vec<Option<SortEntry>>,
+    descending: bool,
+}
+
+enum Run {
+    Memory(std::vec::IntoIter<SortEntry>),
+    File(RunReader),
+}
+
+impl Run {
+    fn next_entry(&mut self) -> Result<Option<SortEntry>, Error> {
+        match self {
+            Run::Memory(entries) => Ok(entries.next()),
+            Run::File(reader) => reader.next_entry(),
+        }
+    }
+}
+
+impl Merge {
+    fn new(mut runs: Vec<Run>, descending: bool) -> Result<Self, Error> {
+        let heads =
+            runs.iter_mut()
+                .map(Run::next_entry)
+                .collect::<Result<_, _>>()?;
+
+        Ok(Merge { runs, heads, descending })
+    }
+
+    fn empty(descending: bool) -> Self {
+        Merge { runs: Vec::new(), heads: Vec::new(), descending }
+    }
+
+    fn next_entry(&mut self) -> Result<Option<SortEntry>, Error> {
+        // Note: a linear scan is fine as there are only a few runs
+        let mut smallest: Option<usize> = None;
+        for (idx, head) in self.heads.iter().enumerate() {
+            let Some(entry) = head else { continue };
+            let is_smaller =
+                match smallest {
+                    None => true,
+                    Some(smallest_idx) => {
+                        let smallest_entry = self.heads[smallest_idx].as_ref().unwrap();
+                        entry.cmp(smallest_entry, self.descending) == Ordering::Less
+                    }
+                };
+            if is_smaller {
+                smallest = Some(idx);
+            }
+        }
+
+        let Some(idx) = smallest else { return Ok(None) };
+        let next_head = self.runs[idx].next_entry()?;
+        Ok(std::mem::replace(&mut self.heads[idx], next_head))
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<SortEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+/* Run files */
+
+/// A temporary file holding a sorted run, which is deleted when dropped
+struct RunFile {
+    path: PathBuf,
+}
+
+struct RunReader {
+    reader:    BufReader<File>,
+    // Keeps the file around until we're done reading it
+    _run_file: RunFile,
+}
+
+fn temp_file_err(io_err: io::Error) -> Error {
+    Error::TempFileFailed(io_err.to_string())
+}
+
+impl RunFile {
+    fn write<I: Iterator<Item=Result<SortEntry, Error>>>(entries: I) -> Result<Self, Error> {
+        static NEXT_RUN_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let file_name = format!("pump-sort-{}-{}", std::process::id(), NEXT_RUN_ID.fetch_add(1, AtomicOrdering::Relaxed));
+        let path = std::env::temp_dir().join(file_name);
+        let file = File::options().write(true).create_new(true).open(&path).map_err(temp_file_err)?;
+        // Clean up from now on, even if writing fails
+        let run_file = RunFile { path };
+
+        let mut writer = BufWriter::new(file);
+        for entry in entries {
+            let entry = entry?;
+            encode(&entry.key, &mut writer).map_err(temp_file_err)?;
+            match &entry.item {
+                None => writer.write_all(&[0]),
+                Some(item) => writer.write_all(&[1]).and_then(|_| encode(item, &mut writer)),
+            }
+            .map_err(temp_file_err)?;
+        }
+        writer.flush().map_err(temp_file_err)?;
+
+        Ok(run_file)
+    }
+
+    fn reader(self) -> Result<RunReader, Error> {
+        let file = File::open(&self.path).map_err(temp_file_err)?;
+        Ok(RunReader { reader: BufReader::new(file), _run_file: self })
+    }
+}
+
+impl Drop for RunFile {
+    fn drop(&mut self) {
+        // Nothing much we can do if this fails
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl RunReader {
+    fn next_entry(&mut self) -> Result<Option<SortEntry>, Error> {
+        // The end of the file can only happen between two entries
+        let mut tag = [0];
+        if self.reader.read(&mut tag).map_err(temp_file_err)? == 0 {
+            return Ok(None);
+        }
+
+        let key = decode_tagged(tag[0], &mut self.reader).map_err(temp_file_err)?;
+        let item =
+            match read_u8(&mut self.reader).map_err(temp_file_err)? {
+                0 => None,
+                _ => Some(decode(&mut self.reader).map_err(temp_file_err)?),
+            };
+
+        Ok(Some(SortEntry { key, item }))
+    }
+}
+
+/* Binary encoding of runtime values */
+
+const TAG_STRING: u8 = 0;
+const TAG_INT: u8 = 1;
+const TAG_FLOAT: u8 = 2;
+const TAG_BOOL: u8 = 3;
+const TAG_LIST: u8 = 4;
+const TAG_NONE: u8 = 5;
+const TAG_SOME: u8 = 6;
+const TAG_TUPLE: u8 = 7;
+
+fn encode<W: Write>(rt_val: &RtVal, writer: &mut W) -> io::Result<()> {
+    match rt_val {
+        RtVal::String(s) => {
+            writer.write_all(&[TAG_STRING])?;
+            writer.write_all(&(s.len() as u64).to_le_bytes())?;
+            writer.write_all(s.as_bytes())
+        }
+        RtVal::Int(n) => {
+            writer.write_all(&[TAG_INT])?;
+            writer.write_all(&n.to_le_bytes())
+        }
+        RtVal::Float(x) => {
+            writer.write_all(&[TAG_FLOAT])?;
+            writer.write_all(&x.to_bits().to_le_bytes())
+        }
+        RtVal::Bool(b) =>
+            writer.write_all(&[TAG_BOOL, *b as u8]),
+        RtVal::List(elems) | RtVal::Tuple(elems) => {
+            let tag = if let RtVal::List(_) = rt_val { TAG_LIST } else { TAG_TUPLE };
+            writer.write_all(&[tag])?;
+            writer.write_all(&(elems.len() as u64).to_le_bytes())?;
+            for elem in elems {
+                encode(elem, writer)?;
+            }
+            Ok(())
+        }
+        RtVal::Maybe(None) =>
+            writer.write_all(&[TAG_NONE]),
+        RtVal::Maybe(Some(v)) => {
+            writer.write_all(&[TAG_SOME])?;
+            encode(v, writer)
+        }
+    }
+}
+
+fn decode<R: Read>(reader: &mut R) -> io::Result<RtVal> {
+    let tag = read_u8(reader)?;
+    decode_tagged(tag, reader)
+}
+
+fn decode_tagged<R: Read>(tag: u8, reader: &mut R) -> io::Result<RtVal> {
+    let rt_val =
+        match tag {
+            TAG_STRING => {
+                let len = read_u64(reader)? as usize;
+                let mut bytes = vec![0; len];
+                reader.read_exact(&mut bytes)?;
+                let s = String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                RtVal::String(s)
+            }
+            TAG_INT => RtVal::Int(read_u64(reader)? as i64),
+            TAG_FLOAT => RtVal::Float(f64::from_bits(read_u64(reader)?)),
+            TAG_BOOL => RtVal::Bool(read_u8(reader)? != 0),
+            TAG_LIST | TAG_TUPLE => {
+                let len = read_u64(reader)? as usize;
+                let elems = (0..len).map(|_| decode(reader)).collect::<io::Result<Vec<_>>>()?;
+                if tag == TAG_LIST { RtVal::List(elems) } else { RtVal::Tuple(elems) }
+            }
+            TAG_NONE => RtVal::Maybe(None),
+            TAG_SOME => RtVal::Maybe(Some(Box::new(decode(reader)?))),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupted sort run")),
+        };
+
+    Ok(rt_val)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
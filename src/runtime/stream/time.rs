@@ -0,0 +1,267 @@
+use std::{collections::{btree_map, BTreeMap}, iter::Peekable, sync::LazyLock};
+
+use regex::{Captures, Regex};
+
+use crate::{compile::{Expr, ParsePos, Position, ReduceOp}, error::Error};
+
+use super::{group, histogram::MAX_BUCKETS, stream_from, ItemFn, RtRes, StreamNode};
+use super::super::{scalar::{self, reduce::Accumulator, ExecScalar, ScalarNode}, Float, RtVal};
+
+// 2024-01-02T03:04:05.678+01:00, with optional time, fraction and time zone
+static ISO_8601: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\d{4})-(\d{2})-(\d{2})(?:[T ](\d{2}):(\d{2})(?::(\d{2})(?:[.,](\d+))?)?)?\s*(Z|[+-]\d{2}:?\d{2})?$").unwrap());
+
+// 10/Oct/2000:13:55:36 -0700, as found in access logs (with or without the brackets)
+static COMMON_LOG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[?(\d{1,2})/([A-Za-z]{3})/(\d{4}):(\d{2}):(\d{2}):(\d{2})(?:\s*([+-]\d{4}))?\]?$").unwrap());
+
+// Seconds since the Unix epoch
+static EPOCH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\d+(?:\.\d+)?$").unwrap());
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+// Timestamps are limited to years 0 to 9999, which keeps bucket arithmetic far from overflowing
+const MIN_SECONDS: i64 = -62_167_219_200;
+const MAX_SECONDS: i64 = 253_402_300_800;
+
+/* BucketByTime */
+
+/// (bucket start, aggregated value) pairs, in chronological order.
+/// Buckets are aligned on the Unix epoch, so one day buckets start at midnight UTC.
+/// Filled buckets get the aggregation of an empty stream: 0 for count and sum,
+/// but nothing for avg, min and max, as there is no value to make up.
+pub(super) struct BucketByTime {
+    duration: Box<ScalarNode>,
+    dur_pos:  ParsePos,
+    ts_fn:    ItemFn,
+    ts_pos:   ParsePos,
+    op:       ReduceOp,
+    agg_pos:  ParsePos,
+    // Applied to each item before it is given to the reducer
+    steps:    Vec<group::GroupStep>,
+    // Whether the empty buckets in between are output too
+    fill:     bool,
+    stream:   Box<StreamNode>,
+    // Available once the data source is exhausted
+    output:   Option<Buckets>,
+}
+
+struct Buckets {
+    aggregated: Peekable<btree_map::IntoIter<i64, Accumulator>>,
+    width:      i64,
+    // Set when filling, to the start of the next bucket to output
+    next_start: Option<i64>,
+    // The aggregation of an empty bucket
+    empty:      RtVal,
+}
+
+impl BucketByTime {
+    pub(super) fn new_node(arguments: Vec<Expr>, fill: bool) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let duration = args_iter.next().unwrap();
+        let ts_fn = args_iter.next().unwrap();
+        let agg = args_iter.next().unwrap();
+        let data_source = args_iter.next().unwrap();
+
+        let (dur_pos, ts_pos, agg_pos) = (duration.position(), ts_fn.position(), agg.position());
+        let (op, steps) = group::aggregation_steps(agg, &data_source);
+        let bucket_by_time = BucketByTime {
+            duration: Box::new(scalar::scalar_from(duration)),
+            dur_pos,
+            ts_fn: ItemFn::new(ts_fn, &data_source),
+            ts_pos,
+            op,
+            agg_pos,
+            steps,
+            fill,
+            stream: Box::new(stream_from(data_source)),
+            output: None,
+        };
+        StreamNode::BucketByTime(bucket_by_time)
+    }
+
+    fn aggregate(&mut self) -> Result<Buckets, Error> {
+        let duration = self.duration.eval()?;
+        let width =
+            parse_duration(duration.str_ref().unwrap())
+                .ok_or_else(|| Error::InvalidDuration { duration: duration.to_string(), err_pos: self.dur_pos })?;
+
+        let mut aggregated = BTreeMap::new();
+        for rt_val in self.stream.as_mut() {
+            let rt_val = rt_val?;
+            let timestamp = self.ts_fn.apply(rt_val.clone())?;
+            let seconds =
+                match &timestamp {
+                    RtVal::String(s) => parse_timestamp(s),
+                    number => number.as_float(),
+                };
+            let seconds = seconds.filter(|s| (MIN_SECONDS as Float..MAX_SECONDS as Float).contains(s));
+            let seconds = seconds.ok_or_else(|| Error::InvalidTimestamp { timestamp: timestamp.to_string(), err_pos: self.ts_pos })?;
+
+            let start = (seconds.floor() as i64).div_euclid(width) * width;
+            let accumulator = aggregated.entry(start).or_insert_with(|| Accumulator::new(self.op, self.agg_pos));
+            if let Some(value) = group::apply_steps(&mut self.steps, rt_val)? {
+                accumulator.add(value)?;
+            }
+        }
+
+        if let (true, Some(first), Some(last)) = (self.fill, aggregated.keys().next(), aggregated.keys().next_back()) {
+            if (last - first) / width >= MAX_BUCKETS {
+                return Err(Error::InvalidBuckets { reason: "too many buckets to fill for the range of timestamps", err_pos: self.dur_pos });
+            }
+        }
+
+        let mut aggregated = aggregated.into_iter().peekable();
+        let next_start = if self.fill { aggregated.peek().map(|(start, _acc)| *start) } else { None };
+        let empty = Accumulator::new(self.op, self.agg_pos).result();
+        Ok(Buckets { aggregated, width, next_start, empty })
+    }
+}
+
+impl Iterator for BucketByTime {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.output.is_none() {
+            match self.aggregate() {
+                Ok(buckets) => self.output = Some(buckets),
+                Err(e) => {
+                    // Don't try again after an error
+                    self.output = Some(Buckets::none());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.output.as_mut().unwrap().next().map(Ok)
+    }
+}
+
+impl Buckets {
+    fn none() -> Self {
+        Buckets { aggregated: BTreeMap::new().into_iter().peekable(), width: 1, next_start: None, empty: RtVal::Int(0) }
+    }
+}
+
+impl Iterator for Buckets {
+    type Item = RtVal;
+
+    fn next(&mut self) -> Option<RtVal> {
+        let &(next_aggregated, _) = self.aggregated.peek()?;
+        let (start, value) =
+            match self.next_start {
+                Some(start) if start < next_aggregated =>
+                    (start, self.empty.clone()),
+                _ => {
+                    let (start, accumulator) = self.aggregated.next().unwrap();
+                    (start, accumulator.result())
+                }
+            };
+
+        if self.next_start.is_some() {
+            // Past the end of time, there is nothing left to fill anyway
+            self.next_start = start.checked_add(self.width);
+        }
+        Some(RtVal::Tuple(vec![RtVal::String(format_timestamp(start)), value]))
+    }
+}
+
+/// A duration in seconds, such as "30s", "5m", "1h" or "1d"
+fn parse_duration(duration: &str) -> Option<i64> {
+    let duration = duration.trim();
+    let (digits, unit) = duration.split_at(duration.find(|c: char| !c.is_ascii_digit())?);
+    let multiplier =
+        match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return None,
+        };
+
+    digits.parse::<i64>().ok()?.checked_mul(multiplier).filter(|seconds| *seconds > 0)
+}
+
+/// Seconds since the Unix epoch.
+/// Timestamps without a time zone are taken as UTC.
+fn parse_timestamp(timestamp: &str) -> Option<Float> {
+    let timestamp = timestamp.trim();
+    if EPOCH.is_match(timestamp) {
+        return timestamp.parse().ok();
+    }
+
+    let num = |caps: &Captures, idx| caps.get(idx).map(|m| m.as_str().parse::<i64>().unwrap()).unwrap_or(0);
+    let (date, time, fraction, zone) =
+        if let Some(caps) = ISO_8601.captures(timestamp) {
+            let fraction = caps.get(7).map(|m| format!("0.{}", m.as_str()).parse().unwrap()).unwrap_or(0.0);
+            ((num(&caps, 1), num(&caps, 2), num(&caps, 3)), (num(&caps, 4), num(&caps, 5), num(&caps, 6)), fraction, caps.get(8).map(|m| m.as_str().replace(':', "")))
+        }
+        else if let Some(caps) = COMMON_LOG.captures(timestamp) {
+            let month_name = caps[2].to_ascii_lowercase();
+            let month = MONTHS.iter().position(|m| *m == month_name)? as i64 + 1;
+            ((num(&caps, 3), month, num(&caps, 1)), (num(&caps, 4), num(&caps, 5), num(&caps, 6)), 0.0, caps.get(7).map(|m| m.as_str().to_string()))
+        }
+        else {
+            return None;
+        };
+
+    let ((year, month, day), (hour, minute, second)) = (date, time);
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // The offset is what the local time is ahead of UTC
+    let offset =
+        match zone {
+            None => 0,
+            Some(zone) if zone == "Z" => 0,
+            Some(zone) => {
+                let sign = if zone.starts_with('-') { -1 } else { 1 };
+                let (hours, minutes) = (zone[1..3].parse::<i64>().ok()?, zone[3..5].parse::<i64>().ok()?);
+                sign * (hours * 3600 + minutes * 60)
+            }
+        };
+
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    Some(seconds as Float + fraction)
+}
+
+/// ISO 8601, in UTC
+fn format_timestamp(seconds: i64) -> String {
+    let (days, secs_of_day) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, secs_of_day / 3600, secs_of_day % 3600 / 60, secs_of_day % 60)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar, see
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,203 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BinaryHeap, HashMap},
+};
+
+use crate::{compile::Expr, error::Error};
+
+use super::{stream_from, CountArg, ItemFn, RtRes, StreamNode};
+use super::super::{Int, RtVal};
+
+/* StreamTop */
+
+/// The items with the largest keys, largest first.
+/// Only the current top items are kept in memory.
+pub(super) struct StreamTop {
+    count:  CountArg,
+    key_fn: ItemFn,
+    stream: Box<StreamNode>,
+    // Available once the data source is exhausted
+    output: Option<std::vec::IntoIter<RtVal>>,
+}
+
+/// Ordered by key, then by reverse arrival, so that the first items win ties
+struct TopEntry {
+    key:  RtVal,
+    seq:  usize,
+    item: RtVal,
+}
+
+impl Ord for TopEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.sort_cmp(&other.key).then(other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for TopEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TopEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TopEntry { }
+
+impl StreamTop {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let count = CountArg::new(args_iter.next().unwrap());
+        let key_fn = args_iter.next().unwrap();
+        let data_source = args_iter.next().unwrap();
+
+        let top = StreamTop {
+            count,
+            key_fn: ItemFn::new(key_fn, &data_source),
+            stream: Box::new(stream_from(data_source)),
+            output: None,
+        };
+        StreamNode::Top(top)
+    }
+
+    fn top(&mut self) -> Result<Vec<RtVal>, Error> {
+        let count = self.count.get()?;
+
+        // A min-heap, so that the smallest of the top items is the one to go
+        let mut heap = BinaryHeap::with_capacity(count.min(1024) + 1);
+        for (seq, rt_val) in self.stream.as_mut().enumerate() {
+            let item = rt_val?;
+            let key = self.key_fn.apply(item.clone())?;
+            heap.push(Reverse(TopEntry { key, seq, item }));
+            if heap.len() > count {
+                heap.pop();
+            }
+        }
+
+        // Note: the ascending order of the reversed entries is the descending order of the keys
+        let top = heap.into_sorted_vec().into_iter().map(|Reverse(entry)| entry.item).collect();
+        Ok(top)
+    }
+}
+
+impl Iterator for StreamTop {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.output.is_none() {
+            match self.top() {
+                Ok(top) => self.output = Some(top.into_iter()),
+                Err(e) => {
+                    self.output = Some(Vec::new().into_iter());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.output.as_mut().unwrap().next().map(Ok)
+    }
+}
+
+/* Frequencies */
+
+/// (count, item) pairs, the most frequent items first.
+/// Items are either all counted exactly, or only the most frequent ones are counted,
+/// approximately, with the Space-Saving algorithm.
+pub(super) struct Frequencies {
+    // Maximum number of counters, for approximate counting
+    count:  Option<CountArg>,
+    stream: Box<StreamNode>,
+    // Available once the data source is exhausted
+    output: Option<std::vec::IntoIter<RtVal>>,
+}
+
+impl Frequencies {
+    pub(super) fn new_node(arguments: Vec<Expr>, approximate: bool) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let count = if approximate { Some(CountArg::new(args_iter.next().unwrap())) } else { None };
+        let data_source = args_iter.next().unwrap();
+
+        let frequencies = Frequencies { count, stream: Box::new(stream_from(data_source)), output: None };
+        StreamNode::Frequencies(frequencies)
+    }
+
+    fn exact(&mut self) -> Result<Vec<(Int, RtVal)>, Error> {
+        let mut counts: HashMap<RtVal, Int> = HashMap::new();
+        for rt_val in self.stream.as_mut() {
+            *counts.entry(rt_val?).or_default() += 1;
+        }
+
+        // Ties are broken by value, like "sort | uniq -c | sort -rn" would do
+        let mut counted: Vec<_> = counts.into_iter().map(|(rt_val, count)| (count, rt_val)).collect();
+        counted.sort_by(|(l_count, l_val), (r_count, r_val)| r_count.cmp(l_count).then_with(|| l_val.sort_cmp(r_val)));
+        Ok(counted)
+    }
+
+    fn space_saving(&mut self, n_counters: usize) -> Result<Vec<(Int, RtVal)>, Error> {
+        // Counters are ordered by count, then by age, so that the oldest of the smallest ones is replaced first
+        let mut counters: HashMap<RtVal, (Int, usize)> = HashMap::new();
+        let mut by_count: BTreeMap<(Int, usize), RtVal> = BTreeMap::new();
+        if n_counters == 0 {
+            return Ok(Vec::new());
+        }
+
+        for (seq, rt_val) in self.stream.as_mut().enumerate() {
+            let rt_val = rt_val?;
+            let new_counter =
+                match counters.get(&rt_val) {
+                    Some(&(count, age)) => {
+                        by_count.remove(&(count, age));
+                        (count + 1, age)
+                    }
+                    None if counters.len() < n_counters =>
+                        (1, seq),
+                    None => {
+                        // The new item takes over the smallest counter, which may overestimate its count
+                        let ((min_count, _age), evicted) = by_count.pop_first().unwrap();
+                        counters.remove(&evicted);
+                        (min_count + 1, seq)
+                    }
+                };
+            by_count.insert(new_counter, rt_val.clone());
+            counters.insert(rt_val, new_counter);
+        }
+
+        // The largest counts first, the oldest counters first in case of a tie
+        let mut counted: Vec<_> = by_count.into_iter().collect();
+        counted.sort_by(|((l_count, l_age), _), ((r_count, r_age), _)| r_count.cmp(l_count).then(l_age.cmp(r_age)));
+        Ok(counted.into_iter().map(|((count, _age), rt_val)| (count, rt_val)).collect())
+    }
+
+    fn frequencies(&mut self) -> Result<Vec<RtVal>, Error> {
+        let counted =
+            match self.count.as_mut().map(CountArg::get) {
+                None => self.exact()?,
+                Some(n_counters) => self.space_saving(n_counters?)?,
+            };
+
+        let output =
+            counted.into_iter()
+                .map(|(count, rt_val)| RtVal::Tuple(vec![RtVal::Int(count), rt_val]))
+                .collect();
+        Ok(output)
+    }
+}
+
+impl Iterator for Frequencies {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        if self.output.is_none() {
+            match self.frequencies() {
+                Ok(output) => self.output = Some(output.into_iter()),
+                Err(e) => {
+                    self.output = Some(Vec::new().into_iter());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.output.as_mut().unwrap().next().map(Ok)
+    }
+}
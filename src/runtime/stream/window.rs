@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+
+use crate::{compile::Expr, error::Error};
+
+use super::{stream_from, CountArg, RtRes, StreamNode};
+use super::super::{Float, RtVal};
+
+/* StreamWindow */
+
+/// Sliding windows of the last items, or non-overlapping chunks of items
+pub(super) struct StreamWindow {
+    size:    CountArg,
+    sliding: bool,
+    current: VecDeque<RtVal>,
+    stream:  Box<StreamNode>,
+}
+
+impl StreamWindow {
+    pub(super) fn new_node(arguments: Vec<Expr>, sliding: bool) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let size = CountArg::new(args_iter.next().unwrap());
+        let data_source = args_iter.next().unwrap();
+
+        let window = StreamWindow { size, sliding, current: VecDeque::new(), stream: Box::new(stream_from(data_source)) };
+        StreamNode::Window(window)
+    }
+
+    fn next_window(&mut self) -> Result<Option<RtVal>, Error> {
+        let size = self.size.get_window_size()?;
+
+        loop {
+            match self.stream.next() {
+                Some(rt_val) => {
+                    if self.sliding && self.current.len() == size {
+                        self.current.pop_front();
+                    }
+                    self.current.push_back(rt_val?);
+                }
+                // Only chunks can be partial, and only the last one
+                None if self.sliding || self.current.is_empty() =>
+                    return Ok(None),
+                None =>
+                    return Ok(Some(Vec::from(std::mem::take(&mut self.current)).into())),
+            }
+
+            if self.current.len() == size {
+                let window =
+                    if self.sliding {
+                        self.current.iter().cloned().collect::<Vec<_>>()
+                    }
+                    else {
+                        Vec::from(std::mem::take(&mut self.current))
+                    };
+                return Ok(Some(window.into()));
+            }
+        }
+    }
+}
+
+impl Iterator for StreamWindow {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        self.next_window().transpose()
+    }
+}
+
+/* MovingAvg */
+
+/// Average of the last items, including the current one.
+/// The first averages are over less items, so that there is one average per item.
+pub(super) struct MovingAvg {
+    size:    CountArg,
+    current: VecDeque<Float>,
+    total:   Float,
+    // The total is recomputed from time to time, to avoid accumulating rounding errors
+    updates: usize,
+    stream:  Box<StreamNode>,
+}
+
+impl MovingAvg {
+    pub(super) fn new_node(arguments: Vec<Expr>) -> StreamNode {
+        let mut args_iter = arguments.into_iter();
+        let size = CountArg::new(args_iter.next().unwrap());
+        let data_source = args_iter.next().unwrap();
+
+        let moving_avg = MovingAvg {
+            size,
+            current: VecDeque::new(),
+            total:   0.0,
+            updates: 0,
+            stream:  Box::new(stream_from(data_source)),
+        };
+        StreamNode::MovingAvg(moving_avg)
+    }
+
+    fn average(&mut self, rt_val: RtVal) -> Result<RtVal, Error> {
+        let size = self.size.get_window_size()?;
+
+        let x = rt_val.as_float().unwrap();
+        if self.current.len() == size {
+            self.total -= self.current.pop_front().unwrap();
+        }
+        self.current.push_back(x);
+        self.total += x;
+
+        self.updates += 1;
+        if self.updates >= size {
+            self.total = self.current.iter().sum();
+            self.updates = 0;
+        }
+
+        Ok(RtVal::Float(self.total / self.current.len() as Float))
+    }
+}
+
+impl Iterator for MovingAvg {
+    type Item = RtRes;
+
+    fn next(&mut self) -> Option<RtRes> {
+        match self.stream.next()? {
+            Ok(rt_val) => Some(self.average(rt_val)),
+            err@Err(_) => Some(err),
+        }
+    }
+}
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "1\n2" | $PUMP 'map (add 1) (map int stdin)'`
+expected=`echo -e "2\n3"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Ints are promoted to floats
+res=`echo -e "1\n2" | $PUMP 'map (mul 1.5) (map int stdin)'`
+expected=`echo -e "1.5\n3.0"`
+assert_eq "$res" "$expected"
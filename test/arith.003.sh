@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Integer division
+res=`echo -e "7\n-7" | $PUMP 'map (div 21) (map int stdin)'`
+expected=`echo -e "3\n-3"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Overflow
+! echo "2" | $PUMP 'map (mul 9223372036854775807) (map int stdin)'
@@ -0,0 +1,3 @@
+#!/bin/bash
+
+! echo "0" | $PUMP 'map (div 1) (map int stdin)'
@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'map (add 1) stdin'
@@ -0,0 +1,7 @@
+#!/bin/bash
+
+# Same as awk '/BEGIN/,/END/', including ranges of a single line
+input="a\nBEGIN 1\nb\nEND 1\nc\nBEGIN END\nd\nBEGIN 2\ne"
+res=`echo -e "$input" | $PUMP 'between m/BEGIN/ m/END/ stdin'`
+expected=`echo -e "$input" | awk '/BEGIN/,/END/'`
+assert_eq "$res" "$expected"
@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Without the boundaries
+res=`echo -e "a\nBEGIN 1\nb\nEND 1\nBEGIN END\nBEGIN 2\nc\nEND 2" | $PUMP 'between_excl m/BEGIN/ m/END/ stdin'`
+expected=`echo -e "b\nc"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,9 @@
+#!/bin/bash
+
+# Only the first range, without reading any further
+res=`yes | $PUMP 'between_once m/y/ m/y/ stdin'`
+assert_eq "$res" "y"
+
+res=`echo -e "1\n2\n3\n2\n3" | $PUMP 'between_once m/2/ m/3/ stdin'`
+expected=`echo -e "2\n3"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,16 @@
+#!/bin/bash
+
+log='1.2.3.4 - - [10/Oct/2000:13:55:36 -0700] "GET / HTTP/1.0" 200 2326
+1.2.3.4 - - [10/Oct/2000:13:55:59 -0700] "GET /a HTTP/1.0" 200 100
+1.2.3.4 - - [10/Oct/2000:13:56:10 -0700] "GET /b HTTP/1.0" 404 50
+1.2.3.4 - - [10/Oct/2000:13:58:01 -0700] "GET /c HTTP/1.0" 200 7'
+
+# Requests per minute from an access log, in UTC
+res=`echo "$log" | $PUMP 'bucket_by_time "1m" (\l -> default "" (c/\[([^\]]+)\]/ l)) count stdin'`
+expected=`echo -e "2000-10-10T20:55:00Z\t2\n2000-10-10T20:56:00Z\t1\n2000-10-10T20:58:00Z\t1"`
+assert_eq "$res" "$expected"
+
+# Empty buckets in between can be filled
+res=`echo "$log" | $PUMP 'bucket_fill "1m" (\l -> default "" (c/\[([^\]]+)\]/ l)) (\g -> sum (map (\l -> int (at 9 (words l))) g)) stdin'`
+expected=`echo -e "2000-10-10T20:55:00Z\t2426\n2000-10-10T20:56:00Z\t50\n2000-10-10T20:57:00Z\t0\n2000-10-10T20:58:00Z\t7"`
+assert_eq "$res" "$expected"
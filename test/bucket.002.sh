@@ -0,0 +1,11 @@
+#!/bin/bash
+
+# ISO 8601 timestamps with time zones, and seconds since the epoch
+res=`echo -e "2024-02-29T23:59:59Z\n2024-03-01 00:00:01+01:00\n1709251200\n2024-03-01\n1709251200.5" | $PUMP 'bucket_by_time "1h" (\l -> l) count stdin'`
+expected=`echo -e "2024-02-29T23:00:00Z\t2\n2024-03-01T00:00:00Z\t3"`
+assert_eq "$res" "$expected"
+
+# Numeric timestamps, with buckets aligned on the epoch
+res=`echo -e "0\n86399\n86400\n-1" | $PUMP 'bucket_by_time "1d" int count stdin'`
+expected=`echo -e "1969-12-31T00:00:00Z\t1\n1970-01-01T00:00:00Z\t2\n1970-01-02T00:00:00Z\t1"`
+assert_eq "$res" "$expected"
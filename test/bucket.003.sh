@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Timestamps are strings or numbers
+invalid_program 'bucket_by_time "1m" m/x/ count stdin'
@@ -0,0 +1,9 @@
+#!/bin/bash
+
+pump="$1"
+
+# Unknown duration unit
+! echo "0" | $pump 'bucket_by_time "1y" int count stdin' 2>/dev/null >/dev/null || exit 1
+
+# Unparsable timestamp
+! echo "yesterday" | $pump 'bucket_by_time "1m" (\l -> l) count stdin' 2>/dev/null >/dev/null
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Step functions see one item at a time, not the whole bucket
+invalid_program 'bucket_by_time "1m" (\l -> "0") (\g -> count (filter (\l -> is_some (max g)) g)) stdin'
@@ -0,0 +1,13 @@
+#!/bin/bash
+
+pump="$1"
+
+# Timestamps beyond year 9999
+! echo "99999999999999999999999" | $pump 'bucket_fill "1m" (\l -> l) count stdin' 2>/dev/null >/dev/null || exit 1
+
+# Too many buckets to fill in between
+! echo -e "0\n100000000000" | $pump 'bucket_fill "1s" int count stdin' 2>/dev/null >/dev/null || exit 1
+
+# Days that don't exist
+! echo "2024-02-31" | $pump 'bucket_by_time "1d" (\l -> l) count stdin' 2>/dev/null >/dev/null || exit 1
+! echo "2023-02-29" | $pump 'bucket_by_time "1d" (\l -> l) count stdin' 2>/dev/null >/dev/null
@@ -0,0 +1,11 @@
+#!/bin/bash
+
+# Filled buckets have no maximum
+res=`echo -e "0\n130" | $PUMP 'bucket_fill "1m" int (\g -> max (map int g)) stdin'`
+expected=`echo -e "1970-01-01T00:00:00Z\t0\n1970-01-01T00:01:00Z\t\n1970-01-01T00:02:00Z\t130"`
+assert_eq "$res" "$expected"
+
+# But they count as zero
+res=`echo -e "0\n130" | $PUMP 'bucket_fill "1m" int (\g -> sum (map int g)) stdin'`
+expected=`echo -e "1970-01-01T00:00:00Z\t0\n1970-01-01T00:01:00Z\t0\n1970-01-01T00:02:00Z\t130"`
+assert_eq "$res" "$expected"
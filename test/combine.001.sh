@@ -0,0 +1,12 @@
+#!/bin/bash
+
+# Two files compared line by line, until either one ends
+left=`mktemp`
+right=`mktemp`
+echo -e "a\nb\nc" > "$left"
+echo -e "a\nB" > "$right"
+
+res=`$PUMP "zip (file \"$left\") (file \"$right\")"`
+expected=`echo -e "a\ta\nb\tB"`
+rm "$left" "$right"
+assert_eq "$res" "$expected"
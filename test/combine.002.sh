@@ -0,0 +1,14 @@
+#!/bin/bash
+
+other=`mktemp`
+echo -e "x\ny" > "$other"
+
+res=`echo -e "1\n2\n3" | $PUMP "concat stdin (file \"$other\")"`
+expected=`echo -e "1\n2\n3\nx\ny"`
+assert_eq "$res" "$expected"
+
+# Interleaving goes on with the longest stream
+res=`echo -e "1\n2\n3" | $PUMP "interleave (file \"$other\") stdin"`
+expected=`echo -e "x\n1\ny\n2\n3"`
+rm "$other"
+assert_eq "$res" "$expected"
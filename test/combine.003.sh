@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Several stdin sources share the same input
+res=`seq 4 | $PUMP 'zip stdin (map int stdin)'`
+expected=`echo -e "1\t2\n3\t4"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'concat stdin (file "/nonexistent/pump-test")'
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Errors on the left are not lost when the right stream is shorter
+! echo "x" | $PUMP 'zip (map int stdin) (take 0 (map int stdin))'
@@ -0,0 +1,7 @@
+#!/bin/bash
+
+# Same as grep, including overlapping contexts
+input=`seq 30`
+res=`echo "$input" | $PUMP 'context 2 1 m/^(5|7|12|20|30)$/ stdin'`
+expected=`echo "$input" | grep -B2 -A1 -E '^(5|7|12|20|30)$'`
+assert_eq "$res" "$expected"
@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# No separator between contiguous groups
+res=`echo -e "a\nx\nb\nx\nc\nd\ne\nx" | $PUMP 'context 0 1 m/x/ stdin'`
+expected=`echo -e "x\nb\nx\nc\n--\nx"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Separators are lines, so the items must be lines too
+invalid_program 'context 1 1 m/1/ (map int stdin)'
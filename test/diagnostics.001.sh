@@ -0,0 +1,4 @@
+#!/bin/bash
+
+res=`echo "a" | $PUMP 'fliter m/a/ stdin' 2>&1 | tail -n 1`
+assert_eq "$res" 'pump: Can'"'"'t resolve identifier "fliter", did you mean "filter"?'
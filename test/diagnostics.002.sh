@@ -0,0 +1,5 @@
+#!/bin/bash
+
+# The whole call is underlined
+res=`echo "a" | $PUMP 'join "," (map len stdin)' 2>&1 | tail -n 2 | head -n 1`
+assert_eq "$res" '          ^^^^^^^^^^^^^'
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+res=`echo "a" | $PUMP 'filter (\x: string -> m/a/ x) (map len stdin)' 2>&1 | tail -n 2 | head -n 1`
+assert_eq "$res" '                               ------------- this stream has item type int'
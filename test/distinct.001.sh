@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a\nb\na\nc\nb" | $PUMP 'distinct stdin'`
+expected=`echo -e "a\nb\nc"`
+assert_eq "$res" "$expected"
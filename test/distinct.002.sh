@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "x1\ny1\nx2\nz2\nw" | $PUMP 'distinct_by c/[0-9]/ stdin'`
+expected=`echo -e "x1\nx2\nw"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`seq 5 | $PUMP 'drop 3 stdin'`
+expected=`echo -e "4\n5"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+res=`seq 5 | $PUMP 'drop 10 stdin'`
+assert_eq "$res" ""
@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Everything from the marker onwards, even items that would be dropped
+res=`echo -e "noise\nnoise\nSTART\nx\nnoise" | $PUMP 'drop_while (\l -> is_some (c/^(noise)$/ l)) stdin'`
+expected=`echo -e "START\nx\nnoise"`
+assert_eq "$res" "$expected"
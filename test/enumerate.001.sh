@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# The equivalent of grep -n
+res=`echo -e "a\nfoo\nb\nfoo" | $PUMP 'filter (\p -> m/foo/ (snd p)) (enumerate stdin)'`
+expected=`echo -e "2\tfoo\n4\tfoo"`
+assert_eq "$res" "$expected"
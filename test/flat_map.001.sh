@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a,b,c\nd" | $PUMP 'flat_map (split ",") stdin'`
+expected=`echo -e "a\nb\nc\nd"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,9 @@
+#!/bin/bash
+
+# Empty words are skipped, as well as lines without any words
+res=`echo -e "  hello   big\tworld \n\n x" | $PUMP 'flat_map words stdin'`
+expected=`echo -e "hello\nbig\nworld\nx"`
+assert_eq "$res" "$expected"
+
+res=`echo -e "1 2\n3 4" | $PUMP 'sum (map int (flat_map words stdin))'`
+assert_eq "$res" "10"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# The function must return a list
+invalid_program 'flat_map len stdin'
@@ -0,0 +1,16 @@
+#!/bin/bash
+
+# Functions can return a stream, started over for each item
+dir=`mktemp -d`
+echo -e "a1\na2" > "$dir/a"
+echo -e "b1" > "$dir/b"
+touch "$dir/empty"
+
+res=`echo -e "$dir/a\n$dir/empty\n$dir/b" | $PUMP 'flat_map file stdin'`
+expected=`echo -e "a1\na2\nb1"`
+assert_eq "$res" "$expected" || exit 1
+
+res=`echo -e "a\nb" | $PUMP "flat_map (\f -> map (\l -> join \":\" [f, l]) (take 1 (file (join \"/\" [\"$dir\", f])))) stdin"`
+rm -r "$dir"
+expected=`echo -e "a:a1\nb:b1"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,8 @@
+#!/bin/bash
+
+res=`seq 5 | $PUMP 'fold 1 mul (map int stdin)'`
+assert_eq "$res" "120"
+
+# The initial value is the result for an empty stream
+res=`echo -n "" | $PUMP 'fold 42 add (map int stdin)'`
+assert_eq "$res" "42"
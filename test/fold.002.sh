@@ -0,0 +1,5 @@
+#!/bin/bash
+
+# The accumulator is promoted to float because of the step function
+res=`seq 2 | $PUMP 'fold 0 (\acc x -> add acc (div (float x) 2)) (map int stdin)'`
+assert_eq "$res" "1.5"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# The step function must return the accumulator type
+invalid_program 'fold "" (\acc x -> len x) stdin'
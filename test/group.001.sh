@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Groups come out in the order in which their key was first seen
+res=`echo -e "b 1\na 2\nb 3\nc 4" | $PUMP 'group_by (at 0) count (map (split " ") stdin)'`
+expected=`echo -e "b\t2\na\t1\nc\t1"`
+assert_eq "$res" "$expected"
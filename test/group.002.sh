@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Sum of the bytes per host, with groups that don't have any valid value
+res=`echo -e "h1 10\nh2 -\nh1 5" | $PUMP 'group_by (\l -> at 0 (split " " l)) (\g -> sum (filter_some (map (\l -> int? (at 1 (split " " l))) g))) stdin'`
+expected=`echo -e "h1\t15\nh2\t0"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+# Sorting by key or by value
+res=`echo -e "b\na\nb\nc\nb\na" | $PUMP 'sort_by fst (group_by (\x -> x) count stdin)'`
+expected=`echo -e "a\t2\nb\t3\nc\t1"`
+assert_eq "$res" "$expected"
+
+res=`echo -e "b\na\nb\nc\nb\na" | $PUMP 'map snd (rsort_by snd (group_by (\x -> x) count stdin))'`
+expected=`echo -e "3\n2\n1"`
+assert_eq "$res" "$expected"
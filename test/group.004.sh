@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Aggregations are computed incrementally, so only some shapes are allowed
+invalid_program 'group_by len (\g -> count (take 2 g)) stdin'
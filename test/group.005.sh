@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Step functions see one item at a time, not the whole group
+invalid_program 'group_by len (\g -> sum (map (\l -> count g) g)) stdin'
@@ -0,0 +1,11 @@
+#!/bin/bash
+
+# Groups sorted by key
+res=`echo -e "b\na\nb\nc\nb\na" | $PUMP 'group_by_key (\x -> x) count stdin'`
+expected=`echo -e "a\t2\nb\t3\nc\t1"`
+assert_eq "$res" "$expected" || exit 1
+
+# Or by value, ties staying in first-seen order
+res=`echo -e "b\na\nb\nc\nb\na\nd" | $PUMP 'group_by_value (\x -> x) count stdin'`
+expected=`echo -e "c\t1\nd\t1\na\t2\nb\t3"`
+assert_eq "$res" "$expected"
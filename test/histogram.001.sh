@@ -0,0 +1,10 @@
+#!/bin/bash
+
+# Fixed-width buckets, including the empty ones in between
+res=`echo -e "1\n5\n12\n35\n-3" | $PUMP 'histogram 10 (map num stdin)'`
+expected=`echo -e "1\t-10.0\t0.0\n2\t0.0\t10.0\n1\t10.0\t20.0\n0\t20.0\t30.0\n1\t30.0\t40.0"`
+assert_eq "$res" "$expected"
+
+res=`echo -e "0.05\n0.25\n0.31" | $PUMP 'histogram 0.1 (map num stdin)'`
+expected=`echo -e "1\t0.0\t0.1\n0\t0.1\t0.2\n1\t0.2\t0.3\n1\t0.3\t0.4"`
+assert_eq "$res" "$expected"
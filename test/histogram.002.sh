@@ -0,0 +1,10 @@
+#!/bin/bash
+
+# Explicit boundaries, with outer buckets only when they're not empty
+res=`echo -e "1\n5\n12\n35\n100" | $PUMP 'histogram [10, 100] (map num stdin)'`
+expected=`echo -e "2\t-inf\t10.0\n2\t10.0\t100.0\n1\t100.0\tinf"`
+assert_eq "$res" "$expected"
+
+res=`echo -e "15" | $PUMP 'histogram [10, 100, 1000] (map num stdin)'`
+expected=`echo -e "1\t10.0\t100.0\n0\t100.0\t1000.0"`
+assert_eq "$res" "$expected"
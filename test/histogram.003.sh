@@ -0,0 +1,12 @@
+#!/bin/bash
+
+# Logarithmic buckets, zero and negative values have their own
+res=`echo -e "0\n0.5\n1\n5\n12\n999\n1000" | $PUMP 'log_hist 10 (map num stdin)'`
+expected=`echo -e "1\t-inf\t0.0\n1\t0.1\t1.0\n2\t1.0\t10.0\n1\t10.0\t100.0\n1\t100.0\t1000.0\n1\t1000.0\t10000.0"`
+assert_eq "$res" "$expected"
+
+# Bars are scaled to the largest count
+res=`echo -e "1\n3\n3\n3\n3\n21" | $PUMP 'bars (histogram 10 (map int stdin))'`
+bar=`printf '#%.0s' {1..40}`
+expected=`echo -e "5\t0.0\t10.0\t$bar\n0\t10.0\t20.0\t\n1\t20.0\t30.0\t########"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,9 @@
+#!/bin/bash
+
+pump="$1"
+
+# Boundaries must be increasing
+! echo "1" | $pump 'histogram [10, 10] (map num stdin)' 2>/dev/null >/dev/null || exit 1
+
+# Logarithm bases must be greater than 1
+! echo "1" | $pump 'log_hist 1 (map num stdin)' 2>/dev/null >/dev/null
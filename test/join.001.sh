@@ -0,0 +1,19 @@
+#!/bin/bash
+
+# Log lines enriched with a lookup file
+users=`mktemp`
+echo -e "u1 alice\nu2 bob\nu3 carol" > "$users"
+key='(\l -> at 0 (split " " l))'
+
+res=`echo -e "u1 login\nu4 login\nu2 logout\nu1 logout" | $PUMP "inner_join $key $key stdin (file \"$users\")"`
+expected=`echo -e "u1 login\tu1 alice\nu2 logout\tu2 bob\nu1 logout\tu1 alice"`
+assert_eq "$res" "$expected"
+
+res=`echo -e "u1 login\nu4 login" | $PUMP "left_join $key $key stdin (file \"$users\")"`
+expected=`echo -e "u1 login\tu1 alice\nu4 login\t"`
+assert_eq "$res" "$expected"
+
+res=`echo -e "u1 login\nu4 login\nu5 login" | $PUMP "anti_join $key $key stdin (file \"$users\")"`
+expected=`echo -e "u4 login\nu5 login"`
+rm "$users"
+assert_eq "$res" "$expected"
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+# Every match is output, in the order of the second stream
+other=`mktemp`
+echo -e "a1\nb1\na2" > "$other"
+
+res=`echo -e "a\nc" | $PUMP "inner_join (\l -> l) (\l -> default \"\" (c/^(.)/ l)) stdin (file \"$other\")"`
+expected=`echo -e "a\ta1\na\ta2"`
+rm "$other"
+assert_eq "$res" "$expected"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Both keys must have the same type
+invalid_program 'inner_join (\l -> l) (\n -> n) stdin (map int stdin)'
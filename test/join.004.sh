@@ -0,0 +1,9 @@
+#!/bin/bash
+
+# A partially applied join on keys, next to the join of list elements
+other=`mktemp`
+echo -e "a\nb" > "$other"
+
+res=`echo -e "b\nc" | $PUMP "let j = inner_join (\l: string -> l) (\l: string -> l) in map (\p -> join \",\" [fst p, snd p]) (j stdin (file \"$other\"))"`
+rm "$other"
+assert_eq "$res" "b,b"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a\nbcd" | $PUMP 'map (\x -> add (len x) 1) stdin'`
+expected=`echo -e "2\n4"`
+assert_eq "$res" "$expected"
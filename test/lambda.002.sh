@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "1\n12\n3" | $PUMP 'map ((\x: int y: int -> mul x y) 2) (map int stdin)'`
+expected=`echo -e "2\n24\n6"`
+assert_eq "$res" "$expected"
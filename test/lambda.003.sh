@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'let f = \x -> x in map f stdin'
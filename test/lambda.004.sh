@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'map (\x: int -> x) stdin'
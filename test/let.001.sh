@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a\nbcd" | $PUMP 'let n: int = 10 in map (\x -> add (len x) n) stdin'`
+expected=`echo -e "11\n13"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a1\nb2\na3" | $PUMP 'let s: stream of string = filter m/a/ stdin in map (\x -> add (len x) 1) s'`
+expected=`echo -e "3\n3"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "ab\ncdef" | $PUMP 'let f: fn (string) -> int = \x -> len x in map (\y -> add (f y) (f "xyz")) stdin'`
+expected=`echo -e "5\n7"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'let n: int = "a" in map (\x -> n) stdin'
@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'let s = stdin in concat s s'
@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'let s = stdin in flatten (map (split ",") s) in s'
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+# Line numbers refer to the input, whatever happens in between
+res=`echo -e "a\nfoo\nb\nfoo" | $PUMP 'map (\l -> lineno) (filter m/foo/ stdin)'`
+expected=`echo -e "2\n4"`
+assert_eq "$res" "$expected"
+
+res=`echo -e "a b\nc" | $PUMP 'map (\w -> lineno) (flat_map words stdin)'`
+expected=`echo -e "1\n1\n2"`
+assert_eq "$res" "$expected"
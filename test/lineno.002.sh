@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# After sorting, the last line read is not the line of the item
+invalid_program 'map (\x -> lineno) (sort stdin)'
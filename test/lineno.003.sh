@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Same with tail, which reads ahead
+invalid_program 'map (\x -> lineno) (tail 2 stdin)'
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# tail_after reads all of its input before emitting anything
+invalid_program 'map (\l -> lineno) (tail_after m/x/ stdin)'
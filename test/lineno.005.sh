@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# The items of the streams of flat_map are not the lines of stdin
+invalid_program 'map (\x -> lineno) (flat_map file stdin)'
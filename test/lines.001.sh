@@ -0,0 +1,13 @@
+#!/bin/bash
+
+res=`seq 1000 | $PUMP 'lines 100 102 stdin'`
+expected=`echo -e "100\n101\n102"`
+assert_eq "$res" "$expected"
+
+# Stops reading after the last line
+res=`yes | $PUMP 'lines 2 3 stdin'`
+expected=`echo -e "y\ny"`
+assert_eq "$res" "$expected"
+
+res=`seq 3 | $PUMP 'lines 3 1 stdin'`
+assert_eq "$res" ""
@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'lines 1 (sub 1 2) stdin'
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a,b,c\nd,e,f" | $PUMP 'map (at 1) (map (split ",") stdin)'`
+expected=`echo -e "b\ne"`
+assert_eq "$res" "$expected"
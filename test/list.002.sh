@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Regex separator and negative index
+res=`echo -e "a  b c\nd e\t f" | $PUMP 'map (at -1) (map (split m/\s+/) stdin)'`
+expected=`echo -e "c\nf"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a,b,c\nd" | $PUMP 'map len (map (split ",") stdin)'`
+expected=`echo -e "3\n1"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a,b,c\nd,e" | $PUMP 'map (join "-") (map (split ",") stdin)'`
+expected=`echo -e "a-b-c\nd-e"`
+assert_eq "$res" "$expected"
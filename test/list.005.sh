@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a,b\nc" | $PUMP 'flatten (map (split ",") stdin)'`
+expected=`echo -e "a\nb\nc"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Index out of bounds
+! echo "a,b" | $PUMP 'map (at 2) (map (split ",") stdin)'
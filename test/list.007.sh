@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Lists can't be printed directly
+invalid_program 'map (split ",") stdin'
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+# List literals
+res=`echo -e "a\nb" | $PUMP 'map (\x -> join "," [x, "-", x]) stdin'`
+expected=`echo -e "a,-,a\nb,-,b"`
+assert_eq "$res" "$expected"
+
+# Mixing ints and floats gives a list of floats
+res=`echo "1" | $PUMP 'map (\x -> at 0 [num x, 2.5]) stdin'`
+assert_eq "$res" '1'
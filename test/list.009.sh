@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# All the elements must have the same type
+invalid_program 'map (\x -> [x, 1]) stdin'
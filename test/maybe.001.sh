@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Lines that are not numbers are dropped
+res=`echo -e "12\nabc\n-3.5" | $PUMP 'filter_some (map num? stdin)'`
+expected=`echo -e "12\n-3.5"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "12\nabc" | $PUMP 'map (default 0) (map num? stdin)'`
+expected=`echo -e "12\n0"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Missing values are printed as empty lines
+res=`echo -e "id=12\nnothing\nid=7" | $PUMP 'map c/id=(\d+)/ stdin'`
+expected=`echo -e "12\n\n7"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a,b\nc" | $PUMP 'map is_some (map (at? 1) (map (split ",") stdin))'`
+expected=`echo -e "true\nfalse"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# The default value must have the type of the optional value
+invalid_program 'map (default "none") (map num? stdin)'
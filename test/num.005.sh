@@ -1,4 +1,4 @@
 #!/bin/bash
 
 res=`echo '23.' | $PUMP 'map num stdin'`
-assert_eq "23" "$res"
\ No newline at end of file
+assert_eq "23.0" "$res"
\ No newline at end of file
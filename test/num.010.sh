@@ -1,4 +1,3 @@
 #!/bin/bash
 
-res=`echo 'naN' | $PUMP 'map num stdin'`
-assert_eq "NaN" "$res"
\ No newline at end of file
+! echo 'naN' | $PUMP 'map num stdin'
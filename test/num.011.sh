@@ -1,4 +1,3 @@
 #!/bin/bash
 
-res=`echo 'nAn' | $PUMP 'map num stdin'`
-assert_eq "NaN" "$res"
\ No newline at end of file
+! echo 'nAn' | $PUMP 'map num stdin'
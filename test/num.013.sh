@@ -1,4 +1,4 @@
 #!/bin/bash
 
 res=`echo '1e6' | $PUMP 'map num stdin'`
-assert_eq "1000000" "$res"
\ No newline at end of file
+assert_eq "1000000.0" "$res"
\ No newline at end of file
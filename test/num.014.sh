@@ -0,0 +1,5 @@
+#!/bin/bash
+
+# Big integers don't lose precision
+res=`echo '9007199254740993' | $PUMP 'map num stdin'`
+assert_eq "9007199254740993" "$res"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+res=`echo '007' | $PUMP 'map num stdin'`
+assert_eq "7" "$res"
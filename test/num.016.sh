@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Not an integer
+! echo '1.5' | $PUMP 'map int stdin'
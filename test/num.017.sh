@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Infinities are not read as numbers either
+res=`echo -e 'inf\n-Infinity\n1e400\n1e300' | $PUMP 'map (\s -> is_some (num? s)) stdin'`
+expected=`echo -e "false\nfalse\nfalse\ntrue"`
+assert_eq "$res" "$expected"
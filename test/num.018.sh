@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Floats always print with a fractional part or an exponent
+res=`echo -e "2\n1e6\n1e300\n0.00001\n-0" | $PUMP 'map float stdin'`
+expected=`echo -e "2.0\n1000000.0\n1e300\n1e-5\n-0.0"`
+assert_eq "$res" "$expected"
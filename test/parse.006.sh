@@ -0,0 +1,4 @@
+#!/bin/bash
+
+res=`echo 'a"b' | $PUMP 'map (join "\"") (map (split "\"") stdin)'`
+assert_eq "$res" 'a"b'
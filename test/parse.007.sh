@@ -0,0 +1,5 @@
+#!/bin/bash
+
+# Several regexes in the same program, with escaped slashes
+res=`echo -e "a/b\nc" | $PUMP 'map s/a\/b/x\/y/ (filter m/\// (filter m/[a-z]/ stdin))'`
+assert_eq "$res" 'x/y'
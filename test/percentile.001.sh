@@ -0,0 +1,12 @@
+#!/bin/bash
+
+# Linear interpolation between the closest ranks
+res=`seq 1 100 | $PUMP 'percentile 90 (map int stdin)'`
+assert_eq "$res" '90.1'
+
+res=`echo -e "7\n1\n3" | $PUMP 'percentile 50 (map int stdin)'`
+assert_eq "$res" '3'
+
+# Nothing for an empty stream
+res=`echo -n "" | $PUMP 'percentile 50 (map int stdin)'`
+assert_eq "$res" ''
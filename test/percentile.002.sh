@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Exact values have no error
+res=`seq 1 10 | $PUMP 'quantiles [0, 25, 50, 99.9, 100] (map int stdin)'`
+expected=`echo -e "0.0\t1.0\t0.0\n25.0\t3.25\t0.0\n50.0\t5.5\t0.0\n99.9\t9.991\t0.0\n100.0\t10.0\t0.0"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+# Too many values to keep in memory: the exact values must be within the error bounds
+res=`seq 1 100000 | $PUMP --percentile-mem 1K 'quantiles [1, 50, 99] (map int stdin)' | awk -F'\t' '
+    $1 == 1  { exact = 1000.99 }
+    $1 == 50 { exact = 50000.5 }
+    $1 == 99 { exact = 99010.01 }
+    { diff = $2 - exact; if (diff < 0) diff = -diff; if ($3 <= 0 || diff > $3) print "out of bounds:", $0 }
+'`
+assert_eq "$res" ''
@@ -0,0 +1,7 @@
+#!/bin/bash
+
+pump="$1"
+
+# Percentiles go from 0 to 100
+! echo "1" | $pump 'percentile 101 (map int stdin)' 2>/dev/null >/dev/null || exit 1
+! echo "1" | $pump 'quantiles [50, -1] (map int stdin)' 2>/dev/null >/dev/null
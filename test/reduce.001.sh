@@ -0,0 +1,4 @@
+#!/bin/bash
+
+res=`echo -e "a\nb\nc" | $PUMP 'count stdin'`
+assert_eq "$res" "3"
@@ -0,0 +1,7 @@
+#!/bin/bash
+
+res=`seq 100 | $PUMP 'sum (map int stdin)'`
+assert_eq "$res" "5050" || exit 1
+
+res=`echo -e "1\n2.5" | $PUMP 'sum (map num stdin)'`
+assert_eq "$res" "3.5"
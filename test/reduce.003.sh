@@ -0,0 +1,7 @@
+#!/bin/bash
+
+res=`echo -e "10\n9\n100" | $PUMP 'max (map int stdin)'`
+assert_eq "$res" "100" || exit 1
+
+res=`echo -e "10\n9\n100" | $PUMP 'min stdin'`
+assert_eq "$res" "10"
@@ -0,0 +1,8 @@
+#!/bin/bash
+
+res=`seq 4 | $PUMP 'avg (map int stdin)'`
+assert_eq "$res" "2.5" || exit 1
+
+# No value for an empty stream
+res=`echo -n "" | $PUMP 'avg (map int stdin)'`
+assert_eq "$res" ""
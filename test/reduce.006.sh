@@ -0,0 +1,4 @@
+#!/bin/bash
+
+pump="$1"
+! echo -e "9223372036854775807\n1" | $pump 'sum (map int stdin)'
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+# The sum of no floats is a float
+res=`echo -n "" | $PUMP 'sum (map float stdin)'`
+assert_eq "$res" "0.0"
+
+# Also for the empty buckets of a time series
+res=`echo -e "0\n130" | $PUMP 'bucket_fill "1m" (\x -> x) sum (map float stdin)'`
+expected=`echo -e "1970-01-01T00:00:00Z\t0.0\n1970-01-01T00:01:00Z\t0.0\n1970-01-01T00:02:00Z\t130.0"`
+assert_eq "$res" "$expected"
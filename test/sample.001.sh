@@ -0,0 +1,12 @@
+#!/bin/bash
+
+# The same seed gives the same sample, in the order of the stream
+first=`seq 1 1000 | $PUMP --seed 42 'sample 10 stdin'`
+second=`seq 1 1000 | $PUMP --seed 42 'sample 10 stdin'`
+assert_eq "$first" "$second"
+assert_eq `echo "$first" | wc -l` '10'
+assert_eq "$first" "`echo "$first" | sort -n`"
+
+# Short streams are kept whole
+res=`seq 1 3 | $PUMP 'sample 5 stdin'`
+assert_eq "$res" "`seq 1 3`"
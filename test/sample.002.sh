@@ -0,0 +1,9 @@
+#!/bin/bash
+
+# Every nth item, starting with the nth one
+res=`seq 1 10 | $PUMP 'every 3 stdin'`
+assert_eq "$res" "`echo -e "3\n6\n9"`"
+
+# Endless streams are fine
+res=`yes | $PUMP 'take 2 (every 1000 stdin)'`
+assert_eq "$res" "`echo -e "y\ny"`"
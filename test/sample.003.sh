@@ -0,0 +1,11 @@
+#!/bin/bash
+
+# Probabilities of 0 and 1 are deterministic
+res=`seq 1 5 | $PUMP 'bernoulli 1 stdin'`
+assert_eq "$res" "`seq 1 5`"
+res=`seq 1 5 | $PUMP 'bernoulli 0 stdin'`
+assert_eq "$res" ''
+
+# About one item in ten
+res=`seq 1 100000 | $PUMP --seed 7 'count (bernoulli 0.1 stdin)'`
+assert_eq `[ "$res" -gt 9000 ] && [ "$res" -lt 11000 ] && echo ok` 'ok'
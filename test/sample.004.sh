@@ -0,0 +1,4 @@
+#!/bin/bash
+
+# Every would never move forward
+invalid_program 'every 0 stdin'
@@ -0,0 +1,9 @@
+#!/bin/bash
+
+pump="$1"
+
+# Seeds are integers
+! echo "1" | $pump --seed x 'stdin' 2>/dev/null >/dev/null || exit 1
+
+# Probabilities go from 0 to 1
+! echo "1" | $pump 'bernoulli 1.5 stdin' 2>/dev/null >/dev/null
@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Running totals
+res=`echo -e "3\n1\n2" | $PUMP 'scan 0 add (map int stdin)'`
+expected=`echo -e "3\n4\n6"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# The initial value can come from a variable
+res=`echo -e "ab\nc\nabcd" | $PUMP 'let init = 10 in scan init (\acc l -> sub acc (len l)) stdin'`
+expected=`echo -e "8\n7\n3"`
+assert_eq "$res" "$expected"
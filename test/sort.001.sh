@@ -0,0 +1,10 @@
+#!/bin/bash
+
+# Numbers are sorted by value, strings lexically
+res=`echo -e "10\n9\n100\n1.5" | $PUMP 'sort (map num stdin)'`
+expected=`echo -e "1.5\n9\n10\n100"`
+assert_eq "$res" "$expected" || exit 1
+
+res=`echo -e "10\n9\n100\n1.5" | $PUMP 'sort stdin'`
+expected=`echo -e "1.5\n10\n100\n9"`
+assert_eq "$res" "$expected"
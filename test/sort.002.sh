@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Items with the same key keep their input order
+res=`echo -e "b2\na2\nc1\nd3\ne1" | $PUMP 'sort_by (\x -> int? (default "0" (c/[0-9]/ x))) stdin'`
+expected=`echo -e "c1\ne1\nb2\na2\nd3"`
+assert_eq "$res" "$expected"
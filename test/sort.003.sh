@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "b2\na2\nc1\nd3\ne1" | $PUMP 'rsort_by c/[0-9]/ stdin'`
+expected=`echo -e "d3\nb2\na2\nc1\ne1"`
+assert_eq "$res" "$expected"
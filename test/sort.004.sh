@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# A tiny memory budget forces the use of temporary files
+res=`seq 5000 | tac | $PUMP --sort-mem 1K 'sort_by int stdin' | md5sum`
+expected=`seq 5000 | md5sum`
+assert_eq "$res" "$expected"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+pump="$1"
+! echo "fail" | $pump --sort-mem 12X 'sort stdin'
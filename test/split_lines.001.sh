@@ -0,0 +1,9 @@
+#!/bin/bash
+
+res=`echo -e "a,b\nc" | $PUMP 'split_lines "," stdin'`
+expected=`echo -e "a\nb\nc"`
+assert_eq "$res" "$expected"
+
+res=`echo -e "a1b22c" | $PUMP 'split_lines m/[0-9]+/ stdin'`
+expected=`echo -e "a\nb\nc"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`seq 10 | $PUMP 'tail 3 stdin'`
+expected=`echo -e "8\n9\n10"`
+assert_eq "$res" "$expected"
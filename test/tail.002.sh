@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`seq 2 | $PUMP 'tail 1000000000 stdin'`
+expected=`echo -e "1\n2"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a\n--\nb\n--\nc\nd" | $PUMP 'tail_after m/^--$/ stdin'`
+expected=`echo -e "c\nd"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# No match: everything is kept
+res=`echo -e "a\nb" | $PUMP 'tail_after (\x -> m/z/ x) stdin'`
+expected=`echo -e "a\nb"`
+assert_eq "$res" "$expected"
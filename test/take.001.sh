@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Stops reading an endless input
+res=`yes | $PUMP 'take 3 stdin'`
+expected=`echo -e "y\ny\ny"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`seq 10 | $PUMP 'let n = 2 in head n (skip n stdin)'`
+expected=`echo -e "3\n4"`
+assert_eq "$res" "$expected"
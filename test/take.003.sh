@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'take (sub 2 5) stdin'
@@ -0,0 +1,8 @@
+#!/bin/bash
+
+# A closed output pipe ends the program quietly
+errors=`mktemp`
+yes | $PUMP 'map len stdin' 2>"$errors" | head -n 2 >/dev/null
+res=`grep -c panicked "$errors"`
+rm "$errors"
+assert_eq "$res" "0"
@@ -0,0 +1,14 @@
+#!/bin/bash
+
+res=`echo -e "1\n2\n10\n3" | $PUMP 'take_while (\x -> m/^.$/ x) stdin'`
+expected=`echo -e "1\n2"`
+assert_eq "$res" "$expected"
+
+# Stops reading at the first failing item
+res=`yes | $PUMP 'take_while m/y/ (take 3 stdin)'`
+expected=`echo -e "y\ny\ny"`
+assert_eq "$res" "$expected"
+
+res=`(echo -e "a\nb\nstop"; yes) | $PUMP 'take_while (\l -> is_some (c/^([a-z])$/ l)) stdin'`
+expected=`echo -e "a\nb"`
+assert_eq "$res" "$expected"
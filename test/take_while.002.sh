@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'take_while len stdin'
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+res=`echo -e "5\n3\n9\n1\n9\n7" | $PUMP 'top 3 int stdin'`
+expected=`echo -e "9\n9\n7"`
+assert_eq "$res" "$expected"
+
+# The first items win ties
+res=`echo -e "aa\nb\ncc\nd" | $PUMP 'top 3 len stdin'`
+expected=`echo -e "aa\ncc\nb"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "b\na\nc\nb\nc\nb\nd" | $PUMP 'frequencies stdin'`
+expected=`echo -e "3\tb\n2\tc\n1\ta\n1\td"`
+assert_eq "$res" "$expected"
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+# Frequent items are found with only a few counters
+res=`(for i in $(seq 1000); do echo "x$i"; [ $((i % 4)) = 0 ] && echo hot; done) | $PUMP 'take 1 (frequent 10 stdin)'`
+assert_eq "$res" "250	hot"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+res=`$PUMP --type 'map (\x: string -> len x)'`
+assert_eq "$res" "fn (stream of string) -> stream of int"
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+res=`$PUMP --type 'let n: number = 3 in n'`
+assert_eq "$res" "number"
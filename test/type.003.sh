@@ -0,0 +1,3 @@
+#!/bin/bash
+
+invalid_program 'let n: stream of = stdin in n'
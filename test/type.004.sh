@@ -0,0 +1,4 @@
+#!/bin/bash
+
+res=`$PUMP --type 'let counts: stream of (int, string) = uniq_c stdin in counts'`
+assert_eq "$res" "stream of (int, string)"
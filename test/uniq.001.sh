@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a\na\nb\na\na" | $PUMP 'uniq stdin'`
+expected=`echo -e "a\nb\na"`
+assert_eq "$res" "$expected"
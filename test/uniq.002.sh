@@ -0,0 +1,5 @@
+#!/bin/bash
+
+res=`echo -e "a\na\nb\na\na\na" | $PUMP 'uniq_c stdin'`
+expected=`echo -e "2\ta\n1\tb\n3\ta"`
+assert_eq "$res" "$expected"
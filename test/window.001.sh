@@ -0,0 +1,9 @@
+#!/bin/bash
+
+# Only full windows are output
+res=`seq 4 | $PUMP 'map (join ",") (window 3 stdin)'`
+expected=`echo -e "1,2,3\n2,3,4"`
+assert_eq "$res" "$expected"
+
+res=`seq 2 | $PUMP 'map (join ",") (window 3 stdin)'`
+assert_eq "$res" ""
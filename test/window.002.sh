@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# The last chunk can be partial
+res=`seq 5 | $PUMP 'map (join ",") (chunks 2 stdin)'`
+expected=`echo -e "1,2\n3,4\n5"`
+assert_eq "$res" "$expected"
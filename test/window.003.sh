@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# One average per item, over the items seen so far at the beginning
+res=`echo -e "10\n20\n60\n0" | $PUMP 'moving_avg 2 (map int stdin)'`
+expected=`echo -e "10.0\n15.0\n40.0\n30.0"`
+assert_eq "$res" "$expected"
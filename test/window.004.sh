@@ -0,0 +1,8 @@
+#!/bin/bash
+
+# Windows can't be empty, which is only known when running
+res=`echo "a" | $PUMP 'map (join ",") (chunks 0 stdin)' 2>&1 | tail -n 1`
+assert_eq "$res" "pump: runtime window size must be at least 1" || exit 1
+
+res=`echo "a" | $PUMP 'map (join ",") (window 0 stdin)' 2>&1 | tail -n 1`
+assert_eq "$res" "pump: runtime window size must be at least 1"